@@ -0,0 +1,128 @@
+//! AES-128 block cipher on top of an [`Executor`], for the RustCrypto `cipher` traits.
+//!
+//! [`Aes128Ecb`] encrypts or decrypts single blocks on the DCP, the modes implemented in pure
+//! Rust (like the ones in the `ctr` or `cbc` crates) can use it as their block cipher.
+
+use cipher::{
+    consts::{U1, U16},
+    inout::InOut,
+    Block, BlockBackend, BlockCipher, BlockClosure, BlockDecrypt, BlockEncrypt, BlockSizeUser,
+    ParBlocksSizeUser,
+};
+
+use crate::{
+    buffer::{DmaBuffer, SecretBuffer},
+    ex::Executor,
+    ops::{Cipher, CipherDir, CryptKey},
+    packet::ControlPacket,
+    prelude::PacketBuilder,
+    Error,
+};
+
+/// AES-128 block cipher, one packet per block.
+pub struct Aes128Ecb<'e, E: Executor> {
+    ex: &'e E,
+    key: CryptKey,
+    /// Key copied in the payload of every packet when using a payload key.
+    payload: SecretBuffer<16>,
+}
+
+impl<'e, E: Executor> Aes128Ecb<'e, E> {
+    /// Uses `key`, passed to the DCP in the packet payload.
+    pub fn new(ex: &'e E, key: &[u8; 16]) -> Self {
+        Self {
+            ex,
+            key: CryptKey::Payload,
+            payload: SecretBuffer::from_array(*key),
+        }
+    }
+
+    /// Uses a key held by the DCP, like one loaded in the key RAM with
+    /// [`DCP::write_key`](crate::dcp::DCP::write_key).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is [`CryptKey::Payload`], use [`new`](Self::new) instead.
+    pub fn with_key(ex: &'e E, key: CryptKey) -> Self {
+        assert!(key != CryptKey::Payload, "payload keys must be passed to Aes128Ecb::new");
+        Self {
+            ex,
+            key,
+            payload: SecretBuffer::new(),
+        }
+    }
+
+    /// Runs the cipher on a single block, in place.
+    pub fn process_block(&self, block: &mut [u8; 16], dir: CipherDir) -> Result<(), Error> {
+        let mut buf = DmaBuffer::from_array(*block);
+        let mut payload = SecretBuffer::<16>::new();
+        let mut builder = PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Ecb)
+            .with_key(self.key)
+            .direction(dir)
+            .in_place(&mut buf)
+            .decr_semaphore();
+        if self.key == CryptKey::Payload {
+            payload.copy_from_slice(&self.payload);
+            builder = builder.payload(&mut payload);
+        }
+        let mut packet: ControlPacket = builder.into();
+        let task = self.ex.exec_one(&mut packet).map_err(Error::Executor)?;
+        nb::block!(task.poll())?;
+        drop(task);
+        block.copy_from_slice(&buf);
+        Ok(())
+    }
+}
+
+impl<E: Executor> BlockSizeUser for Aes128Ecb<'_, E> {
+    type BlockSize = U16;
+}
+
+impl<E: Executor> BlockCipher for Aes128Ecb<'_, E> {}
+
+impl<E: Executor> BlockEncrypt for Aes128Ecb<'_, E> {
+    fn encrypt_with_backend(&self, f: impl BlockClosure<BlockSize = U16>) {
+        f.call(&mut Backend {
+            cipher: self,
+            dir: CipherDir::Encrypt,
+        })
+    }
+}
+
+impl<E: Executor> BlockDecrypt for Aes128Ecb<'_, E> {
+    fn decrypt_with_backend(&self, f: impl BlockClosure<BlockSize = U16>) {
+        f.call(&mut Backend {
+            cipher: self,
+            dir: CipherDir::Decrypt,
+        })
+    }
+}
+
+/// Processes the blocks passed by the `cipher` traits in one direction.
+struct Backend<'a, 'e, E: Executor> {
+    cipher: &'a Aes128Ecb<'e, E>,
+    dir: CipherDir,
+}
+
+impl<E: Executor> BlockSizeUser for Backend<'_, '_, E> {
+    type BlockSize = U16;
+}
+
+impl<E: Executor> ParBlocksSizeUser for Backend<'_, '_, E> {
+    type ParBlocksSize = U1;
+}
+
+impl<E: Executor> BlockBackend for Backend<'_, '_, E> {
+    /// # Panics
+    ///
+    /// Panics if the DCP reports an error.
+    fn proc_block(&mut self, mut block: InOut<'_, '_, Block<Self>>) {
+        let mut data = [0; 16];
+        data.copy_from_slice(block.get_in());
+        self.cipher
+            .process_block(&mut data, self.dir)
+            .expect("DCP cipher failed");
+        block.get_out().copy_from_slice(&data);
+    }
+}
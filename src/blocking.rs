@@ -0,0 +1,79 @@
+//! One-shot operations that block until the DCP is done.
+//!
+//! Each function builds a single packet, runs it on the executor and waits for it. Use the
+//! [`PacketBuilder`] directly to chain packets or to do something else while the DCP works.
+
+use crate::{
+    ex::Executor,
+    hash,
+    ops::{Crc32, Memcopy, Sha1, Sha256},
+    packet::{
+        builder::check_lengths,
+        ControlPacket,
+    },
+    prelude::PacketBuilder,
+    Error,
+};
+
+/// Copies `src` to `dst`.
+///
+/// Returns a [`BufferError`](crate::packet::builder::BufferError) if they have different
+/// lengths.
+pub fn copy<E: Executor>(ex: &E, src: &[u8], dst: &mut [u8]) -> Result<(), Error> {
+    check_lengths(src, dst).map_err(Error::Buffer)?;
+    if dst.is_empty() {
+        return Ok(());
+    }
+    let mut packet: ControlPacket = PacketBuilder::<Memcopy>::new()
+        .buffers_unchecked(src, dst)
+        .decr_semaphore()
+        .into();
+    let task = ex.exec_one(&mut packet).map_err(Error::Executor)?;
+    nb::block!(task.poll())?;
+    Ok(())
+}
+
+/// Fills `dst` with copies of `word`.
+pub fn fill<E: Executor>(ex: &E, word: u32, dst: &mut [u8]) -> Result<(), Error> {
+    if dst.is_empty() {
+        return Ok(());
+    }
+    let mut packet: ControlPacket = PacketBuilder::<Memcopy>::new()
+        .constant(word)
+        .dest(dst)
+        .decr_semaphore()
+        .into();
+    let task = ex.exec_one(&mut packet).map_err(Error::Executor)?;
+    nb::block!(task.poll())?;
+    Ok(())
+}
+
+/// SHA-1 digest of `data`.
+pub fn sha1<E: Executor>(ex: &E, data: &[u8]) -> Result<[u8; 20], Error> {
+    hash::digest::<Sha1, E>(ex, data)
+}
+
+/// SHA-256 digest of `data`.
+pub fn sha256<E: Executor>(ex: &E, data: &[u8]) -> Result<[u8; 32], Error> {
+    hash::digest::<Sha256, E>(ex, data)
+}
+
+/// CRC32 of `data`, see [`Crc32`] for the exact variant.
+pub fn crc32<E: Executor>(ex: &E, data: &[u8]) -> Result<u32, Error> {
+    hash::digest::<Crc32, E>(ex, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock::MockExecutor, packet::builder::BufferError};
+
+    #[test]
+    fn copy_length_mismatch() {
+        let mock = MockExecutor::new();
+        let res = copy(&mock, &[0; 8], &mut [0; 4]);
+        let expected = BufferError::SourceDestLenMismatch { src: 8, dst: 4 };
+        assert!(matches!(res, Err(Error::Buffer(e)) if e == expected));
+        assert!(mock.captured().is_empty());
+    }
+}
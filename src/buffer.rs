@@ -0,0 +1,177 @@
+//! Buffers suited for DCP operations.
+//!
+//! The DCP works on any byte slice, but aligned buffers avoid residual writes and can be cleaned or
+//! invalidated in the data cache without touching unrelated data.
+
+use core::{
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::atomic::{compiler_fence, Ordering},
+};
+
+use crate::packet::Source;
+
+/// Byte buffer aligned to a 32 byte cache line.
+///
+/// `N` must be a multiple of 16, so the buffer always holds a whole number of cipher blocks.
+/// Derefs to `[u8]`, so it can be passed to any method taking a byte slice.
+#[derive(Clone)]
+#[repr(C, align(32))]
+pub struct DmaBuffer<const N: usize>([u8; N]);
+
+impl<const N: usize> DmaBuffer<N> {
+    const VALID_SIZE: () = assert!(N & 0xF == 0, "DmaBuffer size must be a multiple of 16");
+
+    /// Creates a zeroed buffer.
+    pub const fn new() -> Self {
+        Self::from_array([0; N])
+    }
+
+    /// Creates a buffer holding the contents of `array`.
+    pub const fn from_array(array: [u8; N]) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_SIZE;
+        Self(array)
+    }
+
+    /// Uses the buffer as the source of an operation.
+    pub fn as_source(&self) -> Source<'_> {
+        Source::from_slice(&self.0)
+    }
+}
+
+impl<const N: usize> Default for DmaBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Deref for DmaBuffer<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<const N: usize> DerefMut for DmaBuffer<N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// A value aligned to a 32 byte cache line, for packets run by a `CachedSingleChannel`.
+///
+/// A packet is 32 bytes long, so an aligned one fills a whole cache line and its status word
+/// can be invalidated without cleaning the data around it. Wrap a packet, or an array of packets
+/// for [`exec_slice`](crate::ex::Executor::exec_slice), and deref to pass it to the executor.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, align(32))]
+pub struct CacheAligned<T>(pub T);
+
+impl<T> Deref for CacheAligned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for CacheAligned<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Context switching buffer for a [`Scheduler`](crate::ex::Scheduler) using all 4 channels.
+///
+/// When a channel is preempted, the DCP saves its state in
+/// [`CONTEXT_BYTES`](crate::ex::Scheduler::CONTEXT_BYTES) (52 bytes, 13 words) starting at
+/// `52 * channel` in this buffer, so the 4 channels need 208 bytes. The context is written as
+/// 32 bit words and the buffer is aligned to match.
+#[repr(C, align(4))]
+pub struct ContextBuffer([u8; 208]);
+
+impl ContextBuffer {
+    /// Creates a zeroed buffer, can be used to initialize a `static`.
+    pub const fn new() -> Self {
+        Self([0; 208])
+    }
+}
+
+impl Default for ContextBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for ContextBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for ContextBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Byte buffer for key material, zeroed when dropped.
+///
+/// Meant to hold payloads with keys, derefs to `[u8]` so it can be passed to
+/// [`PacketBuilder::payload`](crate::packet::builder::PacketBuilder::payload).
+/// Aligned to a cache line like [`DmaBuffer`].
+#[repr(C, align(32))]
+pub struct SecretBuffer<const N: usize>([u8; N]);
+
+impl<const N: usize> SecretBuffer<N> {
+    /// Creates a zeroed buffer.
+    pub const fn new() -> Self {
+        Self([0; N])
+    }
+
+    /// Creates a buffer holding the contents of `array`.
+    ///
+    /// `array` is moved in, but copies of it left around by the caller are not zeroed.
+    pub const fn from_array(array: [u8; N]) -> Self {
+        Self(array)
+    }
+
+    /// Overwrites the buffer with zeroes.
+    pub fn zeroize(&mut self) {
+        for byte in self.0.iter_mut() {
+            // Volatile writes so the stores are not optimized away
+            unsafe { ptr::write_volatile(byte, 0) };
+        }
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+impl<const N: usize> Default for SecretBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Drop for SecretBuffer<N> {
+    fn drop(&mut self) {
+        self.zeroize()
+    }
+}
+
+impl<const N: usize> Deref for SecretBuffer<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<const N: usize> DerefMut for SecretBuffer<N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
@@ -0,0 +1,134 @@
+//! Data cache maintenance for the buffers accessed by the DCP.
+//!
+//! The DCP reads and writes memory as a bus master, bypassing the Cortex-M7 data cache.
+//! Buffers written by the CPU need to be cleaned before an operation starts and buffers written by
+//! the DCP need to be invalidated after it ends, otherwise either side can see stale data.
+//!
+//! Regions are rounded out to whole cache lines. Lines only partially covered by a buffer are
+//! cleaned before being invalidated so the data sharing the line is not thrown away, but it must
+//! not be written by the CPU while the operation is running: the write back would overwrite what
+//! the DCP wrote. Use [`DmaBuffer`](crate::buffer::DmaBuffer)s to avoid partial lines.
+//!
+//! The packets themselves are written by the CPU right up to their submission, e.g. on the stack,
+//! so they must fill whole lines (see [`CacheAligned`](crate::buffer::CacheAligned)). Their
+//! lines are invalidated without being cleaned.
+
+use cortex_m::{asm, peripheral::CBP};
+
+use crate::packet::ControlPacket;
+
+/// Cortex-M7 data cache line size.
+pub(crate) const LINE: usize = 32;
+
+/// Runs `op` on the address of every cache line overlapping the region.
+fn for_each_line(region: (usize, usize), mut op: impl FnMut(u32)) {
+    let (addr, len) = region;
+    if len == 0 {
+        return;
+    }
+    let start = addr & !(LINE - 1);
+    let end = addr + len;
+    for line in (start..end).step_by(LINE) {
+        op(line as u32);
+    }
+}
+
+/// Writes dirty lines back to memory.
+fn clean(region: (usize, usize)) {
+    let cbp = unsafe { &*CBP::PTR };
+    asm::dsb();
+    for_each_line(region, |line| unsafe { cbp.dccmvac.write(line) });
+    asm::dsb();
+    asm::isb();
+}
+
+/// Writes dirty lines back to memory and drops them from the cache.
+fn clean_invalidate(region: (usize, usize)) {
+    let cbp = unsafe { &*CBP::PTR };
+    asm::dsb();
+    for_each_line(region, |line| unsafe { cbp.dccimvac.write(line) });
+    asm::dsb();
+    asm::isb();
+}
+
+/// Drops the lines from the cache.
+///
+/// Lines only partially covered by the region are cleaned first.
+fn invalidate(region: (usize, usize)) {
+    let (addr, len) = region;
+    let end = addr + len;
+    let cbp = unsafe { &*CBP::PTR };
+    asm::dsb();
+    for_each_line(region, |line| {
+        let partial = (line as usize) < addr || line as usize + LINE > end;
+        if partial {
+            unsafe { cbp.dccimvac.write(line) }
+        } else {
+            unsafe { cbp.dcimvac.write(line) }
+        }
+    });
+    asm::dsb();
+    asm::isb();
+}
+
+/// Drops the lines entirely covered by the region from the cache, without writing them back.
+///
+/// Lines only partially covered by the region are left alone.
+fn discard(region: (usize, usize)) {
+    let (addr, len) = region;
+    let end = addr + len;
+    let cbp = unsafe { &*CBP::PTR };
+    asm::dsb();
+    for_each_line(region, |line| {
+        if line as usize >= addr && line as usize + LINE <= end {
+            unsafe { cbp.dcimvac.write(line) }
+        }
+    });
+    asm::dsb();
+    asm::isb();
+}
+
+fn packet_region(packet: &ControlPacket) -> (usize, usize) {
+    (
+        packet as *const ControlPacket as usize,
+        core::mem::size_of_val(packet),
+    )
+}
+
+/// Prepares the buffers of a packet (and the ones chained to it) to be read by the DCP.
+pub(crate) fn prepare(packet: &ControlPacket) {
+    for packet in unsafe { packet.chain() } {
+        clean_invalidate(packet_region(packet));
+        if let Some(source) = packet.source_region() {
+            clean(source);
+        }
+        if let Some(payload) = packet.payload_region() {
+            clean_invalidate(payload);
+        }
+        if let Some(dest) = packet.dest_region() {
+            clean_invalidate(dest);
+        }
+    }
+}
+
+/// Drops the cached copy of the status word so the one written by the DCP can be read.
+///
+/// Only done for packets filling a whole cache line: cleaning a partial line to keep the data
+/// sharing it could overwrite the status. Called through the
+/// [`sync_status`](crate::ex::Executor::sync_status) hook of `CachedSingleChannel`, the packets
+/// run by the executors without cache maintenance are left alone: they must be in uncached
+/// memory anyway.
+pub(crate) fn sync_status(packet: &ControlPacket) {
+    discard(packet_region(packet));
+}
+
+/// Makes the data written by the DCP visible to the CPU.
+pub(crate) fn finish(packet: &ControlPacket) {
+    discard(packet_region(packet));
+    if let Some(payload) = packet.payload_region() {
+        invalidate(payload);
+    }
+    if let Some(dest) = packet.dest_region() {
+        invalidate(dest);
+    }
+}
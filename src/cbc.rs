@@ -0,0 +1,231 @@
+//! AES-128-CBC over data split in multiple packets, and with keys loaded in the key RAM.
+//!
+//! The DCP keeps the CBC state (the last ciphertext block) in the channel context between
+//! packets. Only the first packet loads the IV from its payload, the next ones leave the cipher
+//! uninitialized and continue the chain. Like the hashers, use it with a single channel executor
+//! and don't run other cipher packets on the channel until the stream is done.
+
+use imxrt_ral::dcp;
+
+use crate::{
+    buffer::SecretBuffer,
+    dcp::{write_key, KeyError},
+    ex::{ExError, Executor},
+    ops::{Cipher, CipherDir, CryptKey},
+    packet::{
+        builder::{check_lengths, BufferError},
+        ControlPacket,
+    },
+    prelude::PacketBuilder,
+    Error,
+};
+
+/// Encrypts or decrypts a CBC stream one packet at a time.
+pub struct CbcStream<'e, E: Executor> {
+    ex: &'e E,
+    key: CryptKey,
+    dir: CipherDir,
+    /// Payload key followed by the IV, the IV is only used by the first packet.
+    payload: SecretBuffer<32>,
+    started: bool,
+}
+
+impl<'e, E: Executor> CbcStream<'e, E> {
+    /// Starts a stream using a key held by the DCP, like one loaded in the key RAM.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is [`CryptKey::Payload`], use [`with_payload_key`](Self::with_payload_key)
+    /// instead.
+    pub fn new(ex: &'e E, key: CryptKey, dir: CipherDir, iv: &[u8; 16]) -> Self {
+        assert!(key != CryptKey::Payload, "payload keys must be passed to with_payload_key");
+        let mut payload = SecretBuffer::new();
+        payload[..16].copy_from_slice(iv);
+        Self {
+            ex,
+            key,
+            dir,
+            payload,
+            started: false,
+        }
+    }
+
+    /// Starts a stream passing `key` to the DCP in the payload of every packet.
+    pub fn with_payload_key(ex: &'e E, key: &[u8; 16], dir: CipherDir, iv: &[u8; 16]) -> Self {
+        let mut payload = SecretBuffer::new();
+        payload[..16].copy_from_slice(key);
+        payload[16..].copy_from_slice(iv);
+        Self {
+            ex,
+            key: CryptKey::Payload,
+            dir,
+            payload,
+            started: false,
+        }
+    }
+
+    /// Processes the next part of the stream from `src` to `dst` and blocks until it's done.
+    ///
+    /// Returns [`SourceDestLenMismatch`](BufferError::SourceDestLenMismatch) if `src` and `dst`
+    /// have different lengths, [`NotBlockAligned`](BufferError::NotBlockAligned) if they are not
+    /// a multiple of 16 bytes long, [`MultiChannel`](ExError::MultiChannel) if the executor may
+    /// run the packets on different channels.
+    pub fn update(&mut self, src: &[u8], dst: &mut [u8]) -> Result<(), Error> {
+        check_lengths(src, dst).map_err(Error::Buffer)?;
+        if self.ex.channel().is_none() {
+            return Err(Error::Executor(ExError::MultiChannel));
+        }
+        if src.len() & 0xF != 0 {
+            return Err(Error::Buffer(BufferError::NotBlockAligned { len: src.len() }));
+        }
+        if src.is_empty() {
+            return Ok(());
+        }
+        let mut payload = SecretBuffer::<32>::new();
+        payload.copy_from_slice(&self.payload);
+        let mut builder = PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Cbc)
+            .with_key(self.key)
+            .direction(self.dir)
+            .buffers_unchecked(src, dst)
+            .payload(&mut payload)
+            .decr_semaphore();
+        if !self.started {
+            builder = builder.cipher_init();
+        }
+        let mut packet: ControlPacket = builder.into();
+        let task = self.ex.exec_one(&mut packet).map_err(Error::Executor)?;
+        nb::block!(task.poll())?;
+        self.started = true;
+        Ok(())
+    }
+}
+
+/// A key loaded in a key RAM slot, to encrypt and decrypt many messages with AES-128-CBC.
+///
+/// The key is written to the DCP once and the packets refer to the slot, so it doesn't travel in
+/// every payload. The slot is overwritten with zeroes when the session is dropped.
+pub struct AesSession<'e, E: Executor> {
+    ex: &'e E,
+    /// The registers of the DCP run by `ex`, to load and clear the key.
+    regs: &'e dcp::RegisterBlock,
+    slot: u8,
+}
+
+impl<'e, E: Executor> AesSession<'e, E> {
+    /// Loads `key` in key RAM slot `slot` and runs the packets on `ex`.
+    ///
+    /// `regs` are the registers of the DCP run by `ex`, e.g. from
+    /// [`SingleChannel::raw`](crate::ex::SingleChannel::raw) or
+    /// [`Scheduler::raw`](crate::ex::Scheduler::raw).
+    pub fn new(
+        ex: &'e E,
+        regs: &'e dcp::RegisterBlock,
+        slot: u8,
+        key: &[u8; 16],
+    ) -> Result<Self, KeyError> {
+        write_key(regs, slot, key)?;
+        Ok(Self { ex, regs, slot })
+    }
+
+    /// Encrypts `data` in place.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is not a multiple of 16 bytes long.
+    pub fn encrypt(&self, iv: &[u8; 16], data: &mut [u8]) -> Result<(), Error> {
+        self.run(CipherDir::Encrypt, iv, data)
+    }
+
+    /// Decrypts `data` in place.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is not a multiple of 16 bytes long.
+    pub fn decrypt(&self, iv: &[u8; 16], data: &mut [u8]) -> Result<(), Error> {
+        self.run(CipherDir::Decrypt, iv, data)
+    }
+
+    fn run(&self, dir: CipherDir, iv: &[u8; 16], data: &mut [u8]) -> Result<(), Error> {
+        assert!(data.len() & 0xF == 0, "CBC data must be a multiple of 16 bytes");
+        if data.is_empty() {
+            return Ok(());
+        }
+        let mut payload = [0; 16];
+        let mut packet: ControlPacket = PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Cbc)
+            .with_key(CryptKey::KeyRam(self.slot))
+            .direction(dir)
+            .in_place(data)
+            .payload(&mut payload)
+            .iv(iv)
+            .decr_semaphore()
+            .into();
+        let task = self.ex.exec_one(&mut packet).map_err(Error::Executor)?;
+        nb::block!(task.poll())?;
+        Ok(())
+    }
+}
+
+impl<E: Executor> Drop for AesSession<'_, E> {
+    fn drop(&mut self) {
+        // The slot was valid when the key was loaded
+        let _ = write_key(self.regs, self.slot, &[0; 16]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockExecutor;
+
+    #[test]
+    fn update_rejects_partial_blocks() {
+        let ex = MockExecutor::new();
+        let mut cbc = CbcStream::with_payload_key(&ex, &[1; 16], CipherDir::Decrypt, &[2; 16]);
+        let src = [0u8; 17];
+        let mut dst = [0u8; 17];
+        let res = cbc.update(&src, &mut dst);
+        assert!(matches!(
+            res,
+            Err(Error::Buffer(BufferError::NotBlockAligned { len: 17 }))
+        ));
+        assert!(ex.captured().is_empty());
+    }
+
+    #[test]
+    fn only_the_first_packet_loads_the_iv() {
+        let ex = MockExecutor::new();
+        let mut cbc = CbcStream::with_payload_key(&ex, &[1; 16], CipherDir::Decrypt, &[2; 16]);
+        let src = [0u8; 64];
+        let mut dst = [0u8; 64];
+        let (first, second) = dst.split_at_mut(32);
+        cbc.update(&src[..32], first).unwrap();
+        cbc.update(&src[32..], second).unwrap();
+
+        let captured = ex.captured();
+        assert_eq!(captured.len(), 2);
+        for packet in captured.iter() {
+            assert_eq!(packet.control1 & 0xFF, Cipher::Aes128Cbc as u32);
+            assert_ne!(packet.control0 & 1 << 11, 0);
+            assert_eq!(packet.control0 & 1 << 8, 0);
+            assert_eq!(packet.bufsize, 32);
+        }
+        assert_ne!(captured[0].control0 & 1 << 9, 0);
+        assert_eq!(captured[1].control0 & 1 << 9, 0);
+    }
+
+    #[test]
+    fn key_ram_streams_select_the_slot() {
+        let ex = MockExecutor::new();
+        let mut cbc = CbcStream::new(&ex, CryptKey::KeyRam(2), CipherDir::Encrypt, &[2; 16]);
+        let src = [0u8; 16];
+        let mut dst = [0u8; 16];
+        cbc.update(&src, &mut dst).unwrap();
+
+        let packet = ex.captured()[0];
+        assert_eq!(packet.control1 >> 8 & 0xFF, 2);
+        assert_eq!(packet.control0 & 1 << 11, 0);
+        assert_eq!(packet.control0 & (1 << 8 | 1 << 9), 1 << 8 | 1 << 9);
+    }
+}
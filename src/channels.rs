@@ -10,17 +10,18 @@ use super::ral::{
     dcp::{RegisterBlock, CHANNELCTRL::ENABLE_CHANNEL::RW as ch},
     read_reg, write_reg,
 };
-use crate::packet::ControlPacket;
-
-/// Marker trait for DCP channels.
+use crate::packet::{ControlPacket, Status};
 
 mod private {
     pub trait Sealed {}
     impl<const N: u8> Sealed for super::Ch<N> {}
 }
 
+/// Marker trait for DCP channels.
 pub trait Channel: private::Sealed {
     const CHANNEL_BIT: u32;
+    /// Channel number, 0 to 3.
+    const CHANNEL_INDEX: u8 = Self::CHANNEL_BIT.trailing_zeros() as u8;
 
     /// Schedules the execution of a packet in the channel.
     fn write_cmdptr(inst: &RegisterBlock, ptr: &ControlPacket);
@@ -30,6 +31,10 @@ pub trait Channel: private::Sealed {
     fn clear_status(inst: &RegisterBlock);
     /// Checks if the channel is in use.
     fn busy(inst: &RegisterBlock) -> bool;
+    /// Tag of the packet the channel processed last, from its status register.
+    fn current_tag(inst: &RegisterBlock) -> u8;
+    /// Status of the packet the channel processed last, from its status register.
+    fn status(inst: &RegisterBlock) -> Status;
 
     /// Enables the channel and clears its status.
     fn enable(inst: &RegisterBlock) {
@@ -50,12 +55,88 @@ pub trait Channel: private::Sealed {
     /// Clears the status and writes a control packet pointer.
     fn clear_and_cmdptr(inst: &RegisterBlock, ptr: &ControlPacket) {
         Self::clear_status(inst);
+        // The packets are being submitted, so the chain is alive
+        #[cfg(feature = "trace")]
+        unsafe {
+            ptr.trace(Self::CHANNEL_INDEX)
+        };
         Self::write_cmdptr(inst, ptr);
     }
 }
 
 pub struct Ch<const N: u8>;
 
+/// A set of DCP channels, stored as a bitmask of [`Channel::CHANNEL_BIT`]s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChannelMask(pub u32);
+
+impl ChannelMask {
+    /// All four channels.
+    pub const ALL: Self = Self(0xF);
+
+    /// Adds a channel to the set.
+    pub fn with<C: Channel>(self) -> Self {
+        Self(self.0 | C::CHANNEL_BIT)
+    }
+
+    /// Adds a channel to the set in place.
+    pub fn insert<C: Channel>(&mut self) {
+        self.0 |= C::CHANNEL_BIT;
+    }
+
+    /// Removes a channel from the set.
+    pub fn remove<C: Channel>(&mut self) {
+        self.0 &= !C::CHANNEL_BIT;
+    }
+
+    /// Checks if the channel is in the set.
+    pub fn contains<C: Channel>(self) -> bool {
+        self.0 & C::CHANNEL_BIT != 0
+    }
+
+    /// Checks if the set is empty.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Channel operations looked up at runtime, for executors managing a variable set of channels.
+pub(crate) struct ChannelOps {
+    pub bit: u32,
+    pub busy: fn(&RegisterBlock) -> bool,
+    pub enable: fn(&RegisterBlock),
+    pub disable: fn(&RegisterBlock),
+    pub clear_status: fn(&RegisterBlock),
+    pub clear_and_cmdptr: fn(&RegisterBlock, &ControlPacket),
+    pub incr_semaphore: fn(&RegisterBlock, u32),
+    pub current_tag: fn(&RegisterBlock) -> u8,
+    pub status: fn(&RegisterBlock) -> Status,
+}
+
+impl ChannelOps {
+    const fn of<C: Channel>() -> Self {
+        Self {
+            bit: C::CHANNEL_BIT,
+            busy: C::busy,
+            enable: C::enable,
+            disable: C::disable,
+            clear_status: C::clear_status,
+            clear_and_cmdptr: C::clear_and_cmdptr,
+            incr_semaphore: C::incr_semaphore,
+            current_tag: C::current_tag,
+            status: C::status,
+        }
+    }
+}
+
+/// Operations of every channel, indexed by channel number.
+pub(crate) const CHANNELS: [ChannelOps; 4] = [
+    ChannelOps::of::<Ch0>(),
+    ChannelOps::of::<Ch1>(),
+    ChannelOps::of::<Ch2>(),
+    ChannelOps::of::<Ch3>(),
+];
+
 pub type Ch0 = Ch<0>;
 pub type Ch1 = Ch<1>;
 pub type Ch2 = Ch<2>;
@@ -87,6 +168,22 @@ macro_rules! clear_status {
     };
 }
 
+macro_rules! current_tag {
+    ( $reg:ident ) => {
+        fn current_tag(inst: &RegisterBlock) -> u8 {
+            read_reg!(ral::dcp, inst, $reg, TAG) as u8
+        }
+    };
+}
+
+macro_rules! status {
+    ( $reg:ident ) => {
+        fn status(inst: &RegisterBlock) -> Status {
+            Status::from_channel(read_reg!(ral::dcp, inst, $reg))
+        }
+    };
+}
+
 macro_rules! busy {
     ( $reg:ident ) => {
         fn busy(inst: &RegisterBlock) -> bool {
@@ -101,6 +198,8 @@ impl Channel for Ch<0> {
     incr_semaphore!(CH0SEMA);
     clear_status!(CH0STAT_CLR);
     busy!(CH0SEMA);
+    current_tag!(CH0STAT);
+    status!(CH0STAT);
 }
 
 impl Channel for Ch<1> {
@@ -109,6 +208,8 @@ impl Channel for Ch<1> {
     incr_semaphore!(CH1SEMA);
     clear_status!(CH1STAT_CLR);
     busy!(CH1SEMA);
+    current_tag!(CH1STAT);
+    status!(CH1STAT);
 }
 
 impl Channel for Ch<2> {
@@ -117,6 +218,8 @@ impl Channel for Ch<2> {
     incr_semaphore!(CH2SEMA);
     clear_status!(CH2STAT_CLR);
     busy!(CH2SEMA);
+    current_tag!(CH2STAT);
+    status!(CH2STAT);
 }
 
 impl Channel for Ch<3> {
@@ -125,4 +228,6 @@ impl Channel for Ch<3> {
     incr_semaphore!(CH3SEMA);
     clear_status!(CH3STAT_CLR);
     busy!(CH3SEMA);
+    current_tag!(CH3STAT);
+    status!(CH3STAT);
 }
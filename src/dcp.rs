@@ -2,10 +2,15 @@
 //!
 //! This module contains structs and functions to clock and unclock the peripheral and manage its
 //! state.
-use core::ops::Deref;
+use core::{
+    ops::Deref,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use imxrt_ral as ral;
-use ral::{dcp, modify_reg, write_reg};
+use ral::{dcp, modify_reg, read_reg, write_reg};
+
+use crate::channels::ChannelMask;
 
 /// Unclocked DCP instance.
 ///
@@ -25,7 +30,20 @@ impl Unclocked {
         // Turn the DCP clock on
         modify_reg!(ral::ccm, ccm, CCGR0, |r| r | ral::ccm::CCGR0::CG5::mask);
 
-        Builder { inst: self.inst }
+        Builder {
+            inst: self.inst,
+            gather_residual_writes: true,
+            context_caching: true,
+        }
+    }
+
+    /// Turns on clocking and enables the DCP with the default configuration.
+    ///
+    /// Same as `clock(ccm).build()`: enables the DCP clock gate in the CCM, resets the peripheral
+    /// and enables residual write gathering and context caching. Use [`clock`](Self::clock) to
+    /// change the configuration before enabling it.
+    pub fn init(self, ccm: &ral::ccm::Instance) -> DCP {
+        self.clock(ccm).build()
     }
 
     /// Releases the DCP instance.
@@ -41,41 +59,260 @@ impl Unclocked {
 
 pub struct Builder {
     pub(crate) inst: dcp::Instance,
+    gather_residual_writes: bool,
+    context_caching: bool,
 }
 
-/// Set DCP configuration before enabling it. (TBD)
+/// Set DCP configuration before enabling it.
 ///
 /// In this state the peripheral is clocked but not enabled.
 impl Builder {
+    /// Gather residual writes for faster unaligned operations. Enabled by default.
+    ///
+    /// When a destination doesn't start or end on a word boundary, the DCP gathers the bytes
+    /// around the boundary into a single word write instead of writing them one by one. It's a
+    /// device-wide setting, and it doesn't change anything for word aligned buffers (like
+    /// [`DmaBuffer`](crate::buffer::DmaBuffer)), so there is no faster path to pick for them.
+    pub fn gather_residual_writes(mut self, enable: bool) -> Self {
+        self.gather_residual_writes = enable;
+        self
+    }
+
+    /// Cache the context of the last channel used, saving context switches when only one
+    /// channel is in use. Enabled by default.
+    pub fn context_caching(mut self, enable: bool) -> Self {
+        self.context_caching = enable;
+        self
+    }
+
     /// Enable the DCP.
     /// 
     /// This function resets the DCP, enables it and sets some necessary register flags.
+    ///
+    /// The result is not checked, use [`try_build`](Self::try_build) to find out if the DCP
+    /// came out of the reset with its clock running.
     pub fn build(self) -> DCP {
-        // Set CLKGATE to zero
-        write_reg!(dcp, self.inst, CTRL_CLR, ral::dcp::CTRL::CLKGATE::mask);
-        // Reset the DCP to the default state
-        // Set the SFTRST bit in the control register high
-        write_reg!(dcp, self.inst, CTRL_SET, ral::dcp::CTRL::SFTRST::mask);
-        // Then set it low to enable operation
-        write_reg!(dcp, self.inst, CTRL_CLR, ral::dcp::CTRL::SFTRST::mask);
-        // Clear DCP status
-        // Sets the first 4 bits from the STAT register to 0, clearing pending interrupts
-        write_reg!(dcp, self.inst, STAT_CLR, ral::dcp::STAT::IRQ::mask);
-
-        // Enable residual writes for faster unaligned operations
-        let ctrl_reg = ral::dcp::CTRL::GATHER_RESIDUAL_WRITES::mask
-        // Context caching
-        | ral::dcp::CTRL::ENABLE_CONTEXT_CACHING::mask;
-        write_reg!(dcp, self.inst, CTRL_SET, ctrl_reg);
-
-        DCP(self.inst)
+        DCP(self.enable())
+    }
+
+    /// Same as [`build`](Self::build), but gives the instance back if the DCP is not
+    /// [`ready`](DCP::ready) after the reset.
+    pub fn try_build(self) -> Result<DCP, Unclocked> {
+        let dcp = DCP(self.enable());
+        if dcp.ready() {
+            Ok(dcp)
+        } else {
+            Err(Unclocked { inst: dcp.0 })
+        }
+    }
+
+    /// Resets the DCP with the chosen configuration.
+    fn enable(self) -> dcp::Instance {
+        let mut ctrl = 0;
+        if self.gather_residual_writes {
+            // Enable residual writes for faster unaligned operations
+            ctrl |= ral::dcp::CTRL::GATHER_RESIDUAL_WRITES::mask;
+        }
+        if self.context_caching {
+            ctrl |= ral::dcp::CTRL::ENABLE_CONTEXT_CACHING::mask;
+        }
+        reset(&self.inst, ctrl);
+        self.inst
     }
 }
 
+/// Resets the DCP to the default state and sets the `ctrl` flags.
+fn reset(inst: &dcp::RegisterBlock, ctrl: u32) {
+    // Set CLKGATE to zero
+    write_reg!(dcp, inst, CTRL_CLR, ral::dcp::CTRL::CLKGATE::mask);
+    // Reset the DCP to the default state
+    // Set the SFTRST bit in the control register high
+    write_reg!(dcp, inst, CTRL_SET, ral::dcp::CTRL::SFTRST::mask);
+    // Then set it low to enable operation
+    write_reg!(dcp, inst, CTRL_CLR, ral::dcp::CTRL::SFTRST::mask);
+    // The soft reset can gate the clock again, make sure it's running
+    write_reg!(dcp, inst, CTRL_CLR, ral::dcp::CTRL::CLKGATE::mask);
+    // Clear DCP status
+    // Sets the first 4 bits from the STAT register to 0, clearing pending interrupts
+    write_reg!(dcp, inst, STAT_CLR, ral::dcp::STAT::IRQ::mask);
+
+    write_reg!(dcp, inst, CTRL_SET, ctrl);
+}
+
 /// Clocked and active DCP peripheral.
 pub struct DCP(pub/*(crate)*/ dcp::Instance);
 
+/// Hardware configuration of the DCP, read from the capability and version registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Number of channels.
+    pub num_channels: u8,
+    /// Number of key RAM slots.
+    pub num_keys: u8,
+    /// The unique key can be selected.
+    pub unique_key: bool,
+    /// Decryption is available.
+    pub decrypt: bool,
+    pub aes128: bool,
+    pub sha1: bool,
+    pub crc32: bool,
+    pub sha256: bool,
+    /// Major, minor and step version of the block.
+    pub version: (u8, u8, u16),
+}
+
+/// Internal state of the DCP, for diagnostics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DebugStatus {
+    /// Channel being processed, if any.
+    pub current_channel: Option<u8>,
+    /// Channels with a pending operation.
+    pub ready_channels: ChannelMask,
+    /// The OTP key was loaded from the fuses.
+    pub otp_key_ready: bool,
+    /// Raw state of the control state machine, read through the debug data register.
+    pub control: u32,
+}
+
+/// Errors encountered while loading a key.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyError {
+    /// The DCP only has 4 key RAM slots.
+    InvalidSlot(u8),
+}
+
+impl core::fmt::Display for KeyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            KeyError::InvalidSlot(slot) => write!(f, "key RAM slot {} doesn't exist", slot),
+        }
+    }
+}
+
+impl core::error::Error for KeyError {}
+
 impl DCP {
+    /// Reads the hardware configuration of the DCP.
+    pub fn capabilities(&self) -> Capabilities {
+        use ral::dcp::CAPABILITY1::{CIPHER_ALGORITHMS::RW as cipher, HASH_ALGORITHMS::RW as hash};
+
+        let (num_keys, num_channels, no_unique_key, no_decrypt) = read_reg!(
+            dcp,
+            self.0,
+            CAPABILITY0,
+            NUM_KEYS,
+            NUM_CHANNELS,
+            DISABLE_UNIQUE_KEY,
+            DISABLE_DECRYPT
+        );
+        let (ciphers, hashes) =
+            read_reg!(dcp, self.0, CAPABILITY1, CIPHER_ALGORITHMS, HASH_ALGORITHMS);
+        let (major, minor, step) = read_reg!(dcp, self.0, VERSION, MAJOR, MINOR, STEP);
+
+        Capabilities {
+            num_channels: num_channels as u8,
+            num_keys: num_keys as u8,
+            unique_key: no_unique_key == 0,
+            decrypt: no_decrypt == 0,
+            aes128: ciphers & cipher::AES128 != 0,
+            sha1: hashes & hash::SHA1 != 0,
+            crc32: hashes & hash::CRC32 != 0,
+            sha256: hashes & hash::SHA256 != 0,
+            version: (major as u8, minor as u8, step as u16),
+        }
+    }
+
+    /// Checks that the DCP clock is not gated by the DCP itself.
+    ///
+    /// Reads back the `CLKGATE` bit of the control register, which the DCP sets out of reset.
+    ///
+    /// **This doesn't catch a DCP gated in the CCM**, e.g. when [`Unclocked::clock`] was
+    /// skipped: writes to a DCP gated there are lost and the register reads don't reflect them.
+    /// The executors only run this check, use [`clocked_in`](Self::clocked_in) where the CCM
+    /// is at hand.
+    pub fn clocked(&self) -> bool {
+        read_reg!(dcp, self.0, CTRL, CLKGATE == 0)
+    }
+
+    /// Same as [`clocked`](Self::clocked), also checking the clock gate of the DCP in the CCM.
+    pub fn clocked_in(&self, ccm: &ral::ccm::Instance) -> bool {
+        read_reg!(ral::ccm, ccm, CCGR0, CG5) != 0 && self.clocked()
+    }
+
+    /// Checks if the DCP is clocked and out of reset, like [`Builder::build`] leaves it.
+    ///
+    /// A `DCP` built from a raw instance can miss the initialization, the executors check this
+    /// before enabling channels that would never run.
+    pub fn ready(&self) -> bool {
+        read_reg!(dcp, self.0, CTRL, SFTRST == 0) && self.clocked()
+    }
+
+    /// Enables or disables residual write gathering, see [`Builder::gather_residual_writes`].
+    ///
+    /// Applies to the packets started afterwards.
+    pub fn set_gather_residual_writes(&self, enable: bool) {
+        let mask = ral::dcp::CTRL::GATHER_RESIDUAL_WRITES::mask;
+        if enable {
+            write_reg!(dcp, self.0, CTRL_SET, mask);
+        } else {
+            write_reg!(dcp, self.0, CTRL_CLR, mask);
+        }
+    }
+
+    /// Reads the internal state of the DCP.
+    ///
+    /// Meant for debugging operations that never complete.
+    pub fn debug_status(&self) -> DebugStatus {
+        let (ready, current, otp_key_ready) =
+            read_reg!(dcp, self.0, STAT, READY_CHANNELS, CUR_CHANNEL, OTP_KEY_READY);
+        write_reg!(dcp, self.0, DBGSELECT, INDEX: CONTROL);
+        let control = read_reg!(dcp, self.0, DBGDATA);
+
+        DebugStatus {
+            // 0 is idle, then channels are numbered from 1
+            current_channel: current.checked_sub(1).map(|ch| ch as u8),
+            ready_channels: ChannelMask(ready),
+            otp_key_ready: otp_key_ready != 0,
+            control,
+        }
+    }
+
+    /// Loads a 128 bit AES key in one of the 4 key RAM slots.
+    ///
+    /// The key bytes are stored in memory order, like a key in the payload, so
+    /// [`key_swap`](crate::packet::builder::PacketBuilder::key_swap) applies to them too.
+    /// Select the slot in a cipher packet with [`KeySelect::Key0`](crate::packet::KeySelect) to
+    /// `Key3`.
+    pub fn write_key(&self, slot: u8, key: &[u8; 16]) -> Result<(), KeyError> {
+        write_key(&self.0, slot, key)
+    }
+
+    /// Resets the DCP, aborting the running operations.
+    ///
+    /// Use it to recover from a hung channel. The configuration, the enabled channels, the context
+    /// buffer and the channel interrupts are restored, so the executor owning the DCP can be used
+    /// again. The buffers of the tasks in flight must be considered invalid afterward, the DCP may have
+    /// written any part of them.
+    pub fn soft_reset(&mut self) {
+        let channels = read_reg!(dcp, self.0, CHANNELCTRL);
+        let context = read_reg!(dcp, self.0, CONTEXT);
+        let ctrl = read_reg!(dcp, self.0, CTRL)
+            & (ral::dcp::CTRL::GATHER_RESIDUAL_WRITES::mask
+                | ral::dcp::CTRL::ENABLE_CONTEXT_CACHING::mask
+                | ral::dcp::CTRL::ENABLE_CONTEXT_SWITCHING::mask
+                | ral::dcp::CTRL::CHANNEL_INTERRUPT_ENABLE::mask);
+
+        reset(&self.0, ctrl);
+
+        write_reg!(dcp, self.0, CH0STAT_CLR, 0xFF);
+        write_reg!(dcp, self.0, CH1STAT_CLR, 0xFF);
+        write_reg!(dcp, self.0, CH2STAT_CLR, 0xFF);
+        write_reg!(dcp, self.0, CH3STAT_CLR, 0xFF);
+        write_reg!(dcp, self.0, CONTEXT, context);
+        write_reg!(dcp, self.0, CHANNELCTRL, channels);
+    }
+
     /// Resets the DCP and disables clock.
     pub fn unclock(self, ccm: &ral::ccm::Instance) -> Unclocked {
         let inst = self.0;
@@ -97,3 +334,48 @@ impl Deref for DCP {
         &self.0
     }
 }
+
+/// Loads `key` in key RAM slot `slot`, see [`DCP::write_key`].
+///
+/// Takes the registers, to load keys while an executor owns the `DCP`.
+pub fn write_key(inst: &dcp::RegisterBlock, slot: u8, key: &[u8; 16]) -> Result<(), KeyError> {
+    if slot >= 4 {
+        return Err(KeyError::InvalidSlot(slot));
+    }
+    // The subword index is incremented on each write to KEYDATA
+    write_reg!(dcp, inst, KEY, INDEX: slot as u32, SUBWORD: 0);
+    for word in key.chunks_exact(4) {
+        let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        write_reg!(dcp, inst, KEYDATA, word);
+    }
+    Ok(())
+}
+
+/// Channels whose interrupt flag was cleared by [`service_irq`] and not yet taken by
+/// [`take_completed`].
+static COMPLETED: AtomicU32 = AtomicU32::new(0);
+
+/// Services the DCP interrupt.
+///
+/// Returns the channels that raised an interrupt and clears their flags.
+/// Only packets with the interrupt flag set raise one on completion.
+///
+/// Apart from resets, the driver only clears the flags here. The channels are also recorded for
+/// [`take_completed`], so `future::on_interrupt` and
+/// [`Scheduler::drain_completed`](crate::ex::Scheduler::drain_completed) can be used together.
+pub fn service_irq(inst: &dcp::RegisterBlock) -> ChannelMask {
+    let irq = read_reg!(dcp, inst, STAT, IRQ);
+    write_reg!(dcp, inst, STAT_CLR, irq);
+    COMPLETED.fetch_or(irq, Ordering::AcqRel);
+    ChannelMask(irq)
+}
+
+/// Returns the channels of `mask` that raised an interrupt since they were last taken, and
+/// forgets them.
+///
+/// Includes the flags cleared by the [`service_irq`] calls made in the meantime, e.g. from the
+/// interrupt handler.
+pub fn take_completed(inst: &dcp::RegisterBlock, mask: ChannelMask) -> ChannelMask {
+    service_irq(inst);
+    ChannelMask(COMPLETED.fetch_and(!mask.0, Ordering::AcqRel) & mask.0)
+}
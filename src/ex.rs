@@ -3,50 +3,224 @@
 //! DCP packets need to be passed to the hardware to be ran.
 //! Executors handle that.
 
-use core::marker::PhantomData;
+use core::{cell::Cell, marker::PhantomData, mem::ManuallyDrop, ptr};
 use imxrt_ral::{dcp, write_reg};
 
 use crate::{
+    buffer::ContextBuffer,
     channels::*,
     dcp::DCP,
-    packet::{Control0Flag, ControlPacket},
+    hash::HashContext,
+    ops::HashSelect,
+    packet::{Control0Flag, ControlPacket, Status},
+    Tag,
 };
 
 /// Errors encountered while queueing a task for execution.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ExError {
     /// All the channels are full
     SlotsFull,
+    /// The DCP clock is gated, the packet would never run.
+    NotClocked,
+    /// The operation keeps its state in the channel context between packets, but the executor
+    /// may run them on different channels.
+    MultiChannel,
+}
+
+impl core::fmt::Display for ExError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ExError::SlotsFull => f.write_str("all the channels are full"),
+            ExError::NotClocked => f.write_str("the DCP is not clocked"),
+            ExError::MultiChannel => {
+                f.write_str("the operation needs an executor running a single channel")
+            }
+        }
+    }
+}
+
+impl core::error::Error for ExError {}
+
+/// `Status::queued` of a packet waiting in a [`Queued`] executor.
+const WAITING: u8 = 1;
+/// `Status::queued` of a packet dropped from a [`Queued`] executor, plus the index of the error.
+const ABANDONED: u8 = 2;
+
+impl ExError {
+    /// Marks a packet dropped from a queue because of this error.
+    fn abandoned(self) -> u8 {
+        ABANDONED + self as u8
+    }
+
+    /// The error a packet was dropped from a queue for.
+    fn from_abandoned(queued: u8) -> Self {
+        match queued - ABANDONED {
+            0 => ExError::SlotsFull,
+            1 => ExError::NotClocked,
+            _ => ExError::MultiChannel,
+        }
+    }
 }
 
 /// Executes [`Task`]s
 pub trait Executor {
     /// Executes a single task.
     ///
+    /// The packet can be the head of a chain built with
+    /// [`chain_to`](crate::packet::builder::PacketBuilder::chain_to), the task completes with the
+    /// last packet of the chain.
+    ///
     /// Returns [`SlotsFull`](ExError::SlotsFull) if the queue (if there is any) is full.
-    fn exec_one<'a>(&self, task: &'a mut ControlPacket<'a>) -> Result<Task<'a>, ExError> {
-        unsafe { self.inner_exec(task) }?;
-        Ok(Task { packet: task })
+    ///
+    /// Panics if a packet other than the last one of the chain decrements the semaphore, or the
+    /// last one doesn't. Debug builds also panic on a packet with a `next` pointer that doesn't
+    /// match its chain flag or is not word aligned.
+    fn exec_one<'a>(&'a self, task: &'a mut ControlPacket<'a>) -> Result<Task<'a>, ExError> {
+        check_chain(task);
+        let channel = unsafe { self.inner_exec(task) }?;
+        Ok(Task::new::<Self>(core::slice::from_mut(task), channel))
     }
 
     /// Same as `exec_one`, but executes a contiguous slice of `Task`s.
     ///
-    /// Panics if slice is empty.
-    fn exec_slice<'a>(&self, tasks: &'a mut [ControlPacket<'a>]) -> Result<Task<'a>, ExError> {
-        let (_, most) = tasks.split_last_mut().unwrap();
-        for task in most {
-            task.control0 = task.control0.flag(Control0Flag::ChainContinuous)
-        }
-        unsafe { self.inner_exec(&mut tasks[0]) }?;
-        Ok(Task { packet: tasks.last_mut().unwrap() })
+    /// The packets are chained, so the whole slice runs on the channel picked for the first one.
+    ///
+    /// Panics if slice is empty, or if the semaphore is not decremented by the last packet only.
+    fn exec_slice<'a>(
+        &'a self,
+        tasks: &'a mut [ControlPacket<'a>],
+    ) -> Result<Task<'a>, ExError> {
+        let channel = unsafe { start_slice(self, tasks) }?;
+        Ok(Task::new::<Self>(tasks, channel))
+    }
+
+    /// Same as `exec_slice`, but the returned task reports which packet failed.
+    ///
+    /// Panics in the same cases as `exec_slice`.
+    fn exec_batch<'a>(
+        &'a self,
+        tasks: &'a mut [ControlPacket<'a>],
+    ) -> Result<BatchTask<'a>, ExError> {
+        self.exec_slice(tasks).map(|task| BatchTask { task })
+    }
+
+    /// Executes a task and passes it to `f`, then waits for the task to complete.
+    ///
+    /// Dropping a [`Task`] waits for the DCP, but a task can be leaked with `mem::forget` while
+    /// its buffers go out of scope and get reused with the DCP still accessing them. The task
+    /// passed to `f` is owned by this function, so the buffers stay borrowed until the operation
+    /// is over whatever `f` does.
+    ///
+    /// Panics in the same cases as `exec_one`.
+    fn exec_scoped<'a, R>(
+        &'a self,
+        task: &'a mut ControlPacket<'a>,
+        f: impl FnOnce(&Task<'a>) -> R,
+    ) -> Result<R, ExError> {
+        let task = self.exec_one(task)?;
+        Ok(f(&task))
+    }
+
+    /// Number of tasks that can be submitted right now without getting
+    /// [`SlotsFull`](ExError::SlotsFull).
+    ///
+    /// Defaults to 1, for executors that always accept a task.
+    fn capacity(&self) -> usize {
+        1
+    }
+
+    /// Number of submitted tasks that are not complete yet.
+    ///
+    /// Defaults to 0, for executors that complete tasks right away.
+    fn len(&self) -> usize {
+        0
+    }
+
+    /// Checks if all the submitted tasks are complete.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The channel running all the tasks, for executors using a single channel.
+    ///
+    /// Defaults to `None`, for executors that may run tasks on different channels.
+    fn channel(&self) -> Option<u8> {
+        None
+    }
+
+    /// Executes a task without returning a [`Task`] to wait on.
+    ///
+    /// The packet and its buffers are borrowed forever, so they can't be reused or freed while
+    /// the DCP accesses them. Returns the number of the channel running the packet, check for
+    /// completion on the channel (e.g. with [`Scheduler::poll_all`] or
+    /// [`SingleChannel::last_tag`]) or with the DCP interrupt. The [`finish`](Self::finish) hook
+    /// is not called, invalidate the data cache before reading what the DCP wrote.
+    ///
+    /// Panics in the same cases as `exec_one`.
+    fn exec_detached(&self, task: &'static mut ControlPacket<'static>) -> Result<u8, ExError> {
+        check_chain(task);
+        unsafe { self.inner_exec(task) }
     }
 
+    /// Hook called by the implementation before a packet is handed to the DCP.
+    ///
+    /// Must make the packet, its source, payload and destination buffers (and the ones of the
+    /// packets chained to it) coherent with main memory, e.g. by cleaning the data cache.
+    /// Does nothing by default, which is fine when the buffers are in uncached memory (like TCM).
+    fn prepare(_task: &ControlPacket) {}
+
+    /// Hook called by [`Task::poll`] once the operation is complete, for every packet of the task.
+    ///
+    /// Must make the payload and destination buffer written by the DCP visible to the CPU, e.g. by
+    /// invalidating the data cache. Does nothing by default.
+    fn finish(_task: &ControlPacket) {}
+
+    /// Hook called by [`Task`] before reading the status of a packet that may still be running.
+    ///
+    /// Must make the status word written by the DCP visible to the CPU, e.g. by invalidating the
+    /// data cache. Does nothing by default.
+    fn sync_status(_task: &ControlPacket) {}
+
     /// Implementation-specific function called by the other methods.
     ///
-    /// # Unsafe
+    /// Returns the number of the channel the packet was queued on.
+    ///
+    /// # Safety
     ///
     /// Implementor must guarantee that the ControlPacket is not moved after execution.
-    unsafe fn inner_exec(&self, task: &mut ControlPacket) -> Result<(), ExError>;
+    unsafe fn inner_exec(&self, task: &mut ControlPacket) -> Result<u8, ExError>;
+}
+
+/// Chains the packets of `tasks` and hands them to `ex`, see [`Executor::exec_slice`].
+///
+/// # Safety
+///
+/// Same as [`Executor::inner_exec`].
+unsafe fn start_slice<E: Executor + ?Sized>(
+    ex: &E,
+    tasks: &mut [ControlPacket],
+) -> Result<u8, ExError> {
+    let (_, most) = tasks.split_last_mut().unwrap();
+    for task in most {
+        task.control0 = task.control0.flag(Control0Flag::ChainContinuous)
+    }
+    check_chain(&tasks[0]);
+    ex.inner_exec(&mut tasks[0])
+}
+
+/// Panics if the chain starting at `task` is not valid, see [`Executor::exec_one`].
+fn check_chain(task: &ControlPacket) {
+    // The packets are borrowed by the caller, so the chain is alive
+    assert!(
+        unsafe { task.valid_chain() },
+        "only the last packet of a chain must decrement the semaphore"
+    );
+    #[cfg(debug_assertions)]
+    unsafe {
+        task.check_links()
+    };
 }
 
 /// A single channel [`Executor`] that does not need a context switch buffer.
@@ -56,8 +230,12 @@ pub struct SingleChannel<C: Channel> {
 }
 
 impl<C: Channel> SingleChannel<C> {
+    /// Takes channel `C` of the DCP.
+    ///
+    /// Returns `None` if the channel is already enabled, or the DCP is not
+    /// [`ready`](DCP::ready).
     pub fn take(inst: DCP) -> Option<Self> {
-        if C::enabled(&inst) {
+        if !inst.ready() || C::enabled(&inst) {
             return None;
         }
         C::clear_status(&inst);
@@ -69,47 +247,422 @@ impl<C: Channel> SingleChannel<C> {
         })
     }
 
-    /// Blocks until tasks are complete and returns a `[Builder]`.
-    pub fn release(self) -> DCP {
-        // block until the channel is free
+    /// Blocks until the channel is free and disables it.
+    fn stop(&self) {
         while C::busy(&self.inst) {}
 
         C::disable(&self.inst);
+    }
 
-        self.inst
+    /// Blocks until the running task is complete and clears the channel status.
+    ///
+    /// Unlike [`release`](Self::release), the executor stays usable.
+    pub fn drain(&self) {
+        while C::busy(&self.inst) {}
+
+        C::clear_status(&self.inst);
+    }
+
+    /// Blocks until tasks are complete and returns the DCP instance.
+    ///
+    /// Dropping the executor also waits and disables the channel, but loses the instance.
+    pub fn release(self) -> DCP {
+        self.stop();
+        // The channel is already stopped, skip Drop
+        let this = ManuallyDrop::new(self);
+        unsafe { ptr::read(&this.inst) }
+    }
+
+    /// Tag of the packet the channel processed last.
+    pub fn last_tag(&self) -> u8 {
+        C::current_tag(&self.inst)
+    }
+
+    /// The DCP registers, to use features the driver doesn't cover with `imxrt-ral`.
+    ///
+    /// Leave the channel of the executor alone while it is running.
+    pub fn raw(&self) -> &dcp::Instance {
+        &self.inst
+    }
+
+    /// Adds a software queue of `N` packets in front of the channel.
+    pub fn with_queue<const N: usize>(self) -> QueuedSingleChannel<C, N> {
+        Queued::new(self)
+    }
+
+    /// Executes a single task that can be awaited.
+    ///
+    /// Enables the channel interrupt, [`on_interrupt`](crate::future::on_interrupt) must be
+    /// called from the DCP interrupt handler to make progress.
+    #[cfg(feature = "async")]
+    pub fn exec_async<'a>(
+        &'a self,
+        task: &'a mut ControlPacket<'a>,
+    ) -> Result<crate::future::AsyncTask<'a>, ExError> {
+        task.control0 = task.control0.flag(Control0Flag::InterruptEnable);
+        write_reg!(dcp, &self.inst, CTRL_SET, C::CHANNEL_BIT);
+        let task = self.exec_one(task)?;
+        let channel = task.channel() as usize;
+        Ok(crate::future::AsyncTask::new(task, channel))
+    }
+}
+
+impl<C: Channel> Drop for SingleChannel<C> {
+    fn drop(&mut self) {
+        self.stop()
     }
 }
 
 impl<C: Channel> Executor for SingleChannel<C> {
-    unsafe fn inner_exec(&self, task: &mut ControlPacket) -> Result<(), ExError> {
+    unsafe fn inner_exec(&self, task: &mut ControlPacket) -> Result<u8, ExError> {
+        if !self.inst.clocked() {
+            return Err(ExError::NotClocked);
+        }
         if C::busy(&self.inst) {
             Err(ExError::SlotsFull)
         } else {
             task.control0.flag(Control0Flag::DecrSemaphore);
+            Self::prepare(task);
             C::clear_and_cmdptr(&self.inst, task);
             C::incr_semaphore(&self.inst, 1);
 
-            Ok(())
+            Ok(C::CHANNEL_INDEX)
         }
     }
+
+    fn capacity(&self) -> usize {
+        !C::busy(&self.inst) as usize
+    }
+
+    fn len(&self) -> usize {
+        C::busy(&self.inst) as usize
+    }
+
+    fn channel(&self) -> Option<u8> {
+        Some(C::CHANNEL_INDEX)
+    }
+}
+
+/// A [`SingleChannel`] executor that maintains the data cache around operations.
+///
+/// Use this when the buffers live in cacheable memory (OCRAM or external RAM). The packets must
+/// be aligned to a cache line, wrap them in a [`CacheAligned`](crate::buffer::CacheAligned).
+/// Submitting a packet that is not aligned panics.
+#[cfg(feature = "cortex-m")]
+pub struct CachedSingleChannel<C: Channel> {
+    inner: SingleChannel<C>,
+}
+
+#[cfg(feature = "cortex-m")]
+impl<C: Channel> CachedSingleChannel<C> {
+    pub fn take(inst: DCP) -> Option<Self> {
+        SingleChannel::take(inst).map(|inner| Self { inner })
+    }
+
+    /// Blocks until tasks are complete and returns the DCP instance.
+    pub fn release(self) -> DCP {
+        self.inner.release()
+    }
+
+    /// The DCP registers, see [`SingleChannel::raw`].
+    pub fn raw(&self) -> &dcp::Instance {
+        self.inner.raw()
+    }
+}
+
+#[cfg(feature = "cortex-m")]
+impl<C: Channel> Executor for CachedSingleChannel<C> {
+    fn prepare(task: &ControlPacket) {
+        crate::cache::prepare(task)
+    }
+
+    fn finish(task: &ControlPacket) {
+        crate::cache::finish(task)
+    }
+
+    fn sync_status(task: &ControlPacket) {
+        crate::cache::sync_status(task)
+    }
+
+    unsafe fn inner_exec(&self, task: &mut ControlPacket) -> Result<u8, ExError> {
+        for packet in task.chain() {
+            let addr = packet as *const ControlPacket as usize;
+            assert!(addr & (crate::cache::LINE - 1) == 0, "packet at {:#x} is not aligned", addr);
+        }
+        Self::prepare(task);
+        self.inner.inner_exec(task)
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn channel(&self) -> Option<u8> {
+        self.inner.channel()
+    }
+}
+
+/// An executor queueing up to `N` packets in front of a single channel executor `E`, while the
+/// channel is busy.
+///
+/// Packets run in submission order (FIFO). The queue advances when a [`Task`] returned by the
+/// executor is polled (which includes [`Task::wait`] and dropping the task), when a packet is
+/// submitted, and when [`advance`](Self::advance) is called. The executor is not `Sync`, so it
+/// can't be shared with the DCP interrupt handler: keep polling the tasks to get the queued packets
+/// running.
+///
+/// Packets are started through `E`, which checks the clock and runs its
+/// [`prepare`](Executor::prepare) hook when the packet leaves the queue. The queue does no data
+/// cache maintenance of its own: a [`QueuedSingleChannel`] needs the packets and buffers in
+/// uncached memory, queue a `CachedSingleChannel` for cacheable memory.
+pub struct Queued<E: Executor, const N: usize> {
+    inner: E,
+    /// The channel of `inner`.
+    channel: u8,
+    /// Packets waiting for the channel, they are borrowed by their [`Task`]s.
+    queue: [Cell<*mut ControlPacket<'static>>; N],
+    head: Cell<usize>,
+    len: Cell<usize>,
+}
+
+/// A [`SingleChannel`] executor queueing up to `N` packets while the channel is busy.
+pub type QueuedSingleChannel<C, const N: usize> = Queued<SingleChannel<C>, N>;
+
+impl<E: Executor, const N: usize> Queued<E, N> {
+    /// Adds a software queue of `N` packets in front of `inner`.
+    ///
+    /// Panics if `inner` doesn't run all the packets on a single [`channel`](Executor::channel).
+    pub fn new(inner: E) -> Self {
+        let channel = inner.channel().expect("the queue needs a single channel executor");
+        Self {
+            inner,
+            channel,
+            queue: [(); N].map(|_| Cell::new(core::ptr::null_mut())),
+            head: Cell::new(0),
+            len: Cell::new(0),
+        }
+    }
+
+    /// Starts the next queued packet if the channel is free.
+    ///
+    /// Returns `true` if a packet was started. If the inner executor rejects the packet, e.g.
+    /// because the DCP clock is gated, the queued packets would never run: they are all dropped
+    /// from the queue, and their tasks complete with the error returned here.
+    pub fn advance(&self) -> Result<bool, ExError> {
+        if self.len.get() == 0 || self.inner.capacity() == 0 {
+            return Ok(false);
+        }
+        let head = self.head.get();
+        // The task borrowing the packet blocks on drop until the packet ran
+        let task = unsafe { &mut *self.queue[head].get() };
+        task.status.queued = 0;
+        if let Err(e) = unsafe { self.inner.inner_exec(task) } {
+            task.status.queued = WAITING;
+            self.abandon(e);
+            return Err(e);
+        }
+        self.queue[head].set(core::ptr::null_mut());
+        self.head.set((head + 1) % N);
+        self.len.set(self.len.get() - 1);
+        Ok(true)
+    }
+
+    /// Drops the queued packets, their tasks complete with `err`.
+    fn abandon(&self, err: ExError) {
+        for _ in 0..self.len.get() {
+            let head = self.head.get();
+            let task = unsafe { &mut *self.queue[head].get() };
+            task.status.queued = err.abandoned();
+            self.queue[head].set(core::ptr::null_mut());
+            self.head.set((head + 1) % N);
+        }
+        self.len.set(0);
+    }
+
+    /// Number of packets waiting for the channel.
+    pub fn queued(&self) -> usize {
+        self.len.get()
+    }
+
+    /// Runs the queued packets until the queue is empty, or drops them if they can't run.
+    fn flush(&self) -> Result<(), ExError> {
+        while self.len.get() > 0 {
+            self.advance()?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: Channel, const N: usize> Queued<SingleChannel<C>, N> {
+    /// The DCP registers, see [`SingleChannel::raw`].
+    pub fn raw(&self) -> &dcp::Instance {
+        self.inner.raw()
+    }
+
+    /// Runs the queued packets, blocks until they are complete and clears the channel status.
+    ///
+    /// Unlike [`release`](Self::release), the executor stays usable. Packets that can't be
+    /// started are dropped like in [`advance`](Self::advance).
+    pub fn drain(&self) {
+        // The tasks of dropped packets report the error
+        let _ = self.flush();
+        self.inner.drain();
+    }
+
+    /// Runs the queued packets, blocks until they are complete and returns the DCP instance.
+    ///
+    /// Dropping the executor also runs the queued packets, then waits and disables the channel.
+    /// Packets that can't be started are dropped like in [`advance`](Self::advance).
+    pub fn release(self) -> DCP {
+        let _ = self.flush();
+        let this = ManuallyDrop::new(self);
+        unsafe { ptr::read(&this.inner) }.release()
+    }
+}
+
+impl<E: Executor, const N: usize> Drop for Queued<E, N> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// An executor with a software queue, advanced by polling its tasks.
+trait Advance {
+    fn advance(&self) -> Result<bool, ExError>;
+}
+
+impl<E: Executor, const N: usize> Advance for Queued<E, N> {
+    fn advance(&self) -> Result<bool, ExError> {
+        Queued::advance(self)
+    }
+}
+
+impl<E: Executor, const N: usize> Executor for Queued<E, N> {
+    /// Same as [`Executor::exec_one`], polling the task advances the queue.
+    fn exec_one<'a>(&'a self, task: &'a mut ControlPacket<'a>) -> Result<Task<'a>, ExError> {
+        check_chain(task);
+        let channel = unsafe { self.inner_exec(task) }?;
+        let task = Task::new::<Self>(core::slice::from_mut(task), channel);
+        Ok(task.with_queue(self))
+    }
+
+    /// Same as [`Executor::exec_slice`], polling the task advances the queue.
+    fn exec_slice<'a>(
+        &'a self,
+        tasks: &'a mut [ControlPacket<'a>],
+    ) -> Result<Task<'a>, ExError> {
+        let channel = unsafe { start_slice(self, tasks) }?;
+        Ok(Task::new::<Self>(tasks, channel).with_queue(self))
+    }
+
+    fn finish(task: &ControlPacket) {
+        E::finish(task)
+    }
+
+    fn sync_status(task: &ControlPacket) {
+        E::sync_status(task)
+    }
+
+    /// Free queue slots, plus the ones of the inner executor.
+    fn capacity(&self) -> usize {
+        N - self.len.get() + self.inner.capacity()
+    }
+
+    /// Queued packets, plus the ones running on the inner executor.
+    fn len(&self) -> usize {
+        self.len.get() + self.inner.len()
+    }
+
+    fn channel(&self) -> Option<u8> {
+        Some(self.channel)
+    }
+
+    unsafe fn inner_exec(&self, task: &mut ControlPacket) -> Result<u8, ExError> {
+        self.advance()?;
+        if self.len.get() == 0 && self.inner.capacity() > 0 {
+            return self.inner.inner_exec(task);
+        }
+        let len = self.len.get();
+        if len == N {
+            return Err(ExError::SlotsFull);
+        }
+        // Not started yet, don't let the task see a stale status
+        task.status.bits = 0;
+        task.status.queued = WAITING;
+        let task = (task as *mut ControlPacket).cast::<ControlPacket<'static>>();
+        self.queue[(self.head.get() + len) % N].set(task);
+        self.len.set(len + 1);
+        Ok(self.channel)
+    }
 }
 
 /// A scheduler that manages multiple channels.
+///
+/// Each submission goes to the next free channel, round-robin. A chain (from
+/// [`exec_slice`](Executor::exec_slice) or
+/// [`chain_to`](crate::packet::builder::PacketBuilder::chain_to)) is a single submission: it
+/// runs entirely on one channel and is never split across channels, so its packets run in order.
+/// Different submissions may run interleaved on different channels.
+///
+/// The scheduler does no data cache maintenance, keep the packets and buffers in uncached memory
+/// (like the DTCM).
 pub struct Scheduler<'a> {
     inst: DCP,
-    _ctx: &'a mut [u8; 208],
+    channels: ChannelMask,
+    /// Channel the search for a free channel starts from.
+    cursor: Cell<u8>,
+    /// Context switching buffer, written by the DCP.
+    ctx: &'a mut [u8],
 }
 
 impl<'a> Scheduler<'a> {
+    /// Size of the context switching buffer used by each channel.
+    pub const CONTEXT_BYTES: usize = 52;
+
     /// Takes a memory region for the context switching buffer and returns a scheduler.
     ///
     /// If you don't want to worry about lifetimes i recommend allocating a static buffer and
     /// being done with it.
-    pub fn new(inst: DCP, buf: &'a mut [u8; 208]) -> Self {
-        Ch0::enable(&inst);
-        Ch1::enable(&inst);
-        Ch2::enable(&inst);
-        Ch3::enable(&inst);
+    ///
+    /// Returns `None` if the DCP is not [`ready`](DCP::ready).
+    pub fn new(inst: DCP, buf: &'a mut ContextBuffer) -> Option<Self> {
+        Self::with_channels(inst, buf, ChannelMask::ALL)
+    }
+
+    /// Returns a scheduler using only the given channels, leaving the others free.
+    ///
+    /// The context of each channel is stored at a fixed offset in the buffer, which must hold
+    /// [`CONTEXT_BYTES`](Self::CONTEXT_BYTES) for every channel up to the highest one used
+    /// (e.g. 104 bytes for `Ch0` and `Ch1`, 208 bytes if `Ch3` is used). The DCP saves the
+    /// context as 32 bit words, so the buffer must be 4 byte aligned like a [`ContextBuffer`].
+    ///
+    /// Returns `None` if the DCP is not [`ready`](DCP::ready).
+    ///
+    /// Panics if `channels` is empty or the buffer is too small or misaligned.
+    pub fn with_channels(
+        inst: DCP,
+        buf: &'a mut [u8],
+        channels: ChannelMask,
+    ) -> Option<Self> {
+        let channels = ChannelMask(channels.0 & ChannelMask::ALL.0);
+        assert!(!channels.is_empty(), "the scheduler needs at least one channel");
+        let needed = (32 - channels.0.leading_zeros()) as usize * Self::CONTEXT_BYTES;
+        assert!(buf.len() >= needed, "the context buffer must be {} bytes long", needed);
+        assert!(
+            buf.as_ptr() as usize & 3 == 0,
+            "the context buffer must be 4 byte aligned"
+        );
+        if !inst.ready() {
+            return None;
+        }
+
+        for ch in CHANNELS.iter().filter(|ch| channels.0 & ch.bit != 0) {
+            (ch.enable)(&inst);
+        }
 
         write_reg!(
             dcp,
@@ -117,51 +670,375 @@ impl<'a> Scheduler<'a> {
             CTRL_SET,
             dcp::CTRL::ENABLE_CONTEXT_SWITCHING::mask
         );
-        write_reg!(dcp, &inst, CONTEXT, buf as *const u8 as u32);
+        write_reg!(dcp, &inst, CONTEXT, buf.as_ptr() as u32);
+
+        Some(Self {
+            inst,
+            channels,
+            cursor: Cell::new(0),
+            ctx: buf,
+        })
+    }
 
-        Self { inst, _ctx: buf }
+    /// The channels used by the scheduler.
+    fn channels(&self) -> impl Iterator<Item = &'static ChannelOps> {
+        let channels = self.channels;
+        CHANNELS.iter().filter(move |ch| channels.0 & ch.bit != 0)
     }
 
     /// Checks if there are channels with nonzero semaphore.
     pub fn busy(&self) -> bool {
-        Ch0::busy(&self.inst)
-            || Ch1::busy(&self.inst)
-            || Ch2::busy(&self.inst)
-            || Ch3::busy(&self.inst)
+        self.channels().any(|ch| (ch.busy)(&self.inst))
     }
 
-    /// Blocks until all channels have completed, disables the channels and returns the DCP instance.
-    pub fn release(self) -> DCP {
+    /// Checks if channel `ch` has a nonzero semaphore.
+    ///
+    /// Panics if `ch` is not a channel number (0 to 3).
+    pub fn channel_busy(&self, ch: u8) -> bool {
+        (CHANNELS[ch as usize].busy)(&self.inst)
+    }
+
+    /// Tag of the packet channel `ch` processed last.
+    ///
+    /// Panics if `ch` is not a channel number (0 to 3).
+    pub fn last_tag(&self, ch: u8) -> u8 {
+        (CHANNELS[ch as usize].current_tag)(&self.inst)
+    }
+
+    /// The DCP registers, to use features the driver doesn't cover with `imxrt-ral`.
+    ///
+    /// Leave the channels of the scheduler and the context buffer alone while they are in use.
+    pub fn raw(&self) -> &dcp::Instance {
+        &self.inst
+    }
+
+    /// Returns the result of the last packet of every channel that raised an interrupt since the
+    /// previous call, clearing the interrupt flags.
+    ///
+    /// The flags are cleared with [`service_irq`](crate::dcp::service_irq), so the channels
+    /// serviced by `future::on_interrupt` in the meantime are reported too, see
+    /// [`take_completed`](crate::dcp::take_completed).
+    ///
+    /// Only packets with the interrupt flag set raise one on completion, and a channel only
+    /// reports the status of the last packet it processed.
+    pub fn drain_completed(
+        &self,
+    ) -> impl Iterator<Item = (u8, core::result::Result<Tag, crate::Error>)> + '_ {
+        let irq = crate::dcp::take_completed(&self.inst, self.channels).0;
+        self.channels()
+            .filter(move |ch| irq & ch.bit != 0)
+            .map(move |ch| {
+                let index = ch.bit.trailing_zeros() as u8;
+                let res = match (ch.status)(&self.inst).poll() {
+                    Ok(tag) => Ok(tag),
+                    Err(nb::Error::Other(e)) => Err(e),
+                    Err(nb::Error::WouldBlock) => unreachable!(),
+                };
+                (index, res)
+            })
+    }
+
+    /// Checks if all the channels of the scheduler are done.
+    ///
+    /// Returns the error of the first channel whose last packet failed, even if other channels
+    /// are still running, `WouldBlock` if any channel has a nonzero semaphore and `Ok` once all
+    /// of them are idle. A channel keeps reporting an error until it gets a new task or
+    /// [`drain`](Self::drain) is called.
+    pub fn poll_all(&self) -> nb::Result<(), crate::Error> {
+        let mut busy = false;
+        for ch in self.channels() {
+            if let Err(nb::Error::Other(e)) = (ch.status)(&self.inst).poll() {
+                return Err(nb::Error::Other(e));
+            }
+            busy |= (ch.busy)(&self.inst);
+        }
+        if busy {
+            Err(nb::Error::WouldBlock)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The channels of the scheduler that can take a task right away.
+    pub fn free_channels(&self) -> ChannelMask {
+        let free = self
+            .channels()
+            .filter(|ch| !(ch.busy)(&self.inst))
+            .fold(0, |mask, ch| mask | ch.bit);
+        ChannelMask(free)
+    }
+
+    /// Executes a task on channel `C`, instead of the next free one.
+    ///
+    /// Returns [`SlotsFull`](ExError::SlotsFull) if `C` is busy, even if other channels are free.
+    ///
+    /// Panics if `C` is not one of the channels of the scheduler, and in the same cases as
+    /// [`exec_one`](Executor::exec_one).
+    pub fn exec_on<'t, C: Channel>(
+        &self,
+        task: &'t mut ControlPacket<'t>,
+    ) -> Result<Task<'t>, ExError> {
+        assert!(
+            self.channels.0 & C::CHANNEL_BIT != 0,
+            "channel {} is not used by the scheduler",
+            C::CHANNEL_INDEX
+        );
+        check_chain(task);
+        if !self.inst.clocked() {
+            return Err(ExError::NotClocked);
+        }
+        if C::busy(&self.inst) {
+            return Err(ExError::SlotsFull);
+        }
+        Self::prepare(task);
+        C::clear_and_cmdptr(&self.inst, task);
+        C::incr_semaphore(&self.inst, 1);
+        Ok(Task::new::<Self>(core::slice::from_mut(task), C::CHANNEL_INDEX))
+    }
+
+    /// Submits a task, same as [`exec_one`](Executor::exec_one).
+    ///
+    /// Returns [`SlotsFull`](ExError::SlotsFull) right away if all the channels are busy.
+    pub fn try_submit<'t>(
+        &'t self,
+        task: &'t mut ControlPacket<'t>,
+    ) -> Result<Task<'t>, ExError> {
+        self.exec_one(task)
+    }
+
+    /// Submits a task, spinning until one of the channels is free.
+    ///
+    /// Panics in the same cases as [`exec_one`](Executor::exec_one).
+    pub fn submit_blocking<'t>(
+        &self,
+        task: &'t mut ControlPacket<'t>,
+    ) -> Result<Task<'t>, ExError> {
+        check_chain(task);
+        let channel = loop {
+            match unsafe { self.inner_exec(task) } {
+                Err(ExError::SlotsFull) => core::hint::spin_loop(),
+                res => break res?,
+            }
+        };
+        Ok(Task::new::<Self>(core::slice::from_mut(task), channel))
+    }
+
+    /// Copies the context saved by the DCP for channel `ch`, e.g. to resume a hash later.
+    ///
+    /// The DCP only writes the context to memory when it switches away from the channel, and
+    /// every time if context caching is disabled with
+    /// [`Builder::context_caching`](crate::dcp::Builder::context_caching). Save it once the
+    /// channel is idle.
+    ///
+    /// Panics if `ch` is not used by the scheduler or is busy.
+    pub fn save_context(&self, ch: u8) -> HashContext {
+        let range = self.context_range(ch);
+        let mut ctx = HashContext::new();
+        for (dst, src) in ctx.0.iter_mut().zip(&self.ctx[range]) {
+            // Written by the DCP behind the compiler's back
+            *dst = unsafe { ptr::read_volatile(src) };
+        }
+        ctx
+    }
+
+    /// Restores a context saved with [`save_context`](Self::save_context) in channel `ch`.
+    ///
+    /// The next packet on the channel continues the saved hash, it must not initialize it. The
+    /// DCP only reloads the context from memory if context caching is disabled or another
+    /// channel ran in between.
+    ///
+    /// Panics if `ch` is not used by the scheduler or is busy.
+    pub fn restore_context(&mut self, ch: u8, ctx: &HashContext) {
+        let range = self.context_range(ch);
+        for (dst, src) in self.ctx[range].iter_mut().zip(&ctx.0) {
+            unsafe { ptr::write_volatile(dst, *src) };
+        }
+    }
+
+    /// Bytes of the context buffer holding the context of channel `ch`.
+    fn context_range(&self, ch: u8) -> core::ops::Range<usize> {
+        let ops = &CHANNELS[ch as usize];
+        assert!(self.channels.0 & ops.bit != 0, "channel {} is not used by the scheduler", ch);
+        assert!(!(ops.busy)(&self.inst), "channel {} is busy", ch);
+        let start = ch as usize * Self::CONTEXT_BYTES;
+        start..start + Self::CONTEXT_BYTES
+    }
+
+    /// Resets the DCP, see [`DCP::soft_reset`].
+    pub fn soft_reset(&mut self) {
+        self.inst.soft_reset()
+    }
+
+    /// Blocks until all channels have completed, then disables them and context switching.
+    fn stop(&self) {
         while self.busy() {}
 
-        Ch0::disable(&self.inst);
-        Ch1::disable(&self.inst);
-        Ch2::disable(&self.inst);
-        Ch3::disable(&self.inst);
+        for ch in self.channels() {
+            (ch.disable)(&self.inst);
+        }
+        // The context buffer is about to be released
+        write_reg!(
+            dcp,
+            &self.inst,
+            CTRL_CLR,
+            dcp::CTRL::ENABLE_CONTEXT_SWITCHING::mask
+        );
+    }
 
-        self.inst
+    /// Blocks until all channels have completed and clears their status.
+    ///
+    /// Unlike [`release`](Self::release), the scheduler stays usable. The errors of the channels
+    /// are cleared too, check [`poll_all`](Self::poll_all) or
+    /// [`drain_completed`](Self::drain_completed) first to get them.
+    pub fn drain(&self) {
+        while self.busy() {}
+
+        for ch in self.channels() {
+            (ch.clear_status)(&self.inst);
+        }
+    }
+
+    /// Blocks until all channels have completed, disables the channels and returns the DCP instance.
+    ///
+    /// Dropping the scheduler also waits and disables the channels, but loses the instance.
+    pub fn release(self) -> DCP {
+        self.stop();
+        let this = ManuallyDrop::new(self);
+        unsafe { ptr::read(&this.inst) }
+    }
+}
+
+impl Drop for Scheduler<'_> {
+    fn drop(&mut self) {
+        self.stop()
     }
 }
 
 impl<'a> Executor for Scheduler<'a> {
-    unsafe fn inner_exec(&self, task: &mut ControlPacket) -> Result<(), ExError> {
-        if !Ch3::busy(&self.inst) {
-            Ch3::clear_and_cmdptr(&self.inst, task);
-            Ch3::incr_semaphore(&self.inst, 1);
-        } else if !Ch2::busy(&self.inst) {
-            Ch2::clear_and_cmdptr(&self.inst, task);
-            Ch2::incr_semaphore(&self.inst, 1);
-        } else if !Ch1::busy(&self.inst) {
-            Ch1::clear_and_cmdptr(&self.inst, task);
-            Ch1::incr_semaphore(&self.inst, 1);
-        } else if !Ch0::busy(&self.inst) {
-            Ch0::clear_and_cmdptr(&self.inst, task);
-            Ch0::incr_semaphore(&self.inst, 1);
-        } else {
-            return Err(ExError::SlotsFull);
+    unsafe fn inner_exec(&self, task: &mut ControlPacket) -> Result<u8, ExError> {
+        if !self.inst.clocked() {
+            return Err(ExError::NotClocked);
         }
-        Ok(())
+        Self::prepare(task);
+        // Round-robin, so independent tasks are spread over the channels
+        let index = next_free(self.cursor.get(), self.free_channels()).ok_or(ExError::SlotsFull)?;
+        self.cursor.set((index + 1) % CHANNELS.len() as u8);
+        let ch = &CHANNELS[index as usize];
+        (ch.clear_and_cmdptr)(&self.inst, task);
+        (ch.incr_semaphore)(&self.inst, 1);
+        Ok(index)
     }
+
+    /// Number of free channels.
+    fn capacity(&self) -> usize {
+        self.free_channels().0.count_ones() as usize
+    }
+
+    /// Number of busy channels.
+    fn len(&self) -> usize {
+        self.channels().filter(|ch| (ch.busy)(&self.inst)).count()
+    }
+
+    /// The channel of the scheduler, if it only uses one.
+    fn channel(&self) -> Option<u8> {
+        match self.channels.0.count_ones() {
+            1 => Some(self.channels.0.trailing_zeros() as u8),
+            _ => None,
+        }
+    }
+}
+
+/// The first channel of `free` at or after `cursor`, wrapping around.
+fn next_free(cursor: u8, free: ChannelMask) -> Option<u8> {
+    let len = CHANNELS.len() as u8;
+    (cursor..cursor + len)
+        .map(|i| i % len)
+        .find(|&i| free.0 & 1 << i != 0)
+}
+
+/// A submission recorded by a [`TagTracker`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubmissionHandle {
+    index: usize,
+    tag: Tag,
+}
+
+impl SubmissionHandle {
+    /// Slot of the submission in the tracker.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Tag of the submitted packet.
+    pub fn tag(&self) -> Tag {
+        self.tag
+    }
+}
+
+/// Matches completed packets to their submission by tag.
+///
+/// Packets running on different channels complete in any order, the tag copied by the DCP in
+/// the [`Status`] is what ties a result to the packet it comes from. Tags must be unique among
+/// the pending submissions.
+pub struct TagTracker<const N: usize> {
+    tags: [Option<Tag>; N],
+}
+
+impl<const N: usize> TagTracker<N> {
+    pub const fn new() -> Self {
+        Self { tags: [None; N] }
+    }
+
+    /// Records the submission of a packet tagged `tag`.
+    ///
+    /// Returns `None` if all the slots are taken or `tag` is already pending.
+    pub fn track(&mut self, tag: Tag) -> Option<SubmissionHandle> {
+        if self.tags.contains(&Some(tag)) {
+            return None;
+        }
+        let index = self.tags.iter().position(Option::is_none)?;
+        self.tags[index] = Some(tag);
+        Some(SubmissionHandle { index, tag })
+    }
+
+    /// Returns the submission a completed packet belongs to and frees its slot.
+    ///
+    /// Returns `None` if the packet isn't complete or its tag wasn't tracked.
+    pub fn resolve(&mut self, status: &Status) -> Option<SubmissionHandle> {
+        if let Err(nb::Error::WouldBlock) = status.poll() {
+            return None;
+        }
+        self.resolve_tag(status.tag)
+    }
+
+    /// Same as [`resolve`](Self::resolve) for a tag returned by a completed task.
+    pub fn resolve_tag(&mut self, tag: Tag) -> Option<SubmissionHandle> {
+        let index = self.tags.iter().position(|t| *t == Some(tag))?;
+        self.tags[index] = None;
+        Some(SubmissionHandle { index, tag })
+    }
+
+    /// Number of submissions not resolved yet.
+    pub fn pending(&self) -> usize {
+        self.tags.iter().filter(|t| t.is_some()).count()
+    }
+}
+
+impl<const N: usize> Default for TagTracker<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Progress of a [`Task`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskStatus {
+    /// Waiting in a software queue, not handed to the DCP yet.
+    Idle,
+    /// Handed to the DCP, not complete yet.
+    Running,
+    /// Complete, successfully or not.
+    Done,
 }
 
 /// Task object to poll for completion
@@ -169,12 +1046,177 @@ impl<'a> Executor for Scheduler<'a> {
 /// The [Drop] implementation on this waits for completion of the operation and then discards the
 /// result to prevent the DCP from holding a dangling pointers to the work packet and the buffers.
 pub struct Task<'a> {
-    packet: &'a mut ControlPacket<'a>,
+    /// The packets executed by the task, the last one of the chain reports the completion.
+    packets: &'a mut [ControlPacket<'a>],
+    /// Channel running the packets.
+    channel: u8,
+    /// [`Executor::finish`] hook of the executor running the task.
+    finish: fn(&ControlPacket),
+    /// [`Executor::sync_status`] hook of the executor running the task.
+    sync_status: fn(&ControlPacket),
+    finished: Cell<bool>,
+    /// Software queue the task is waiting in, advanced when polling.
+    queue: Option<&'a dyn Advance>,
 }
 
-impl Task<'_> {
+impl<'a> Task<'a> {
+    fn new<E: Executor + ?Sized>(packets: &'a mut [ControlPacket<'a>], channel: u8) -> Self {
+        Self {
+            packets,
+            channel,
+            finish: E::finish,
+            sync_status: E::sync_status,
+            finished: Cell::new(false),
+            queue: None,
+        }
+    }
+
+    fn with_queue(mut self, queue: &'a dyn Advance) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    /// Reads the progress of the task, without completing it like [`poll`](Self::poll) does.
+    pub fn state(&self) -> TaskStatus {
+        if self.finished.get() {
+            return TaskStatus::Done;
+        }
+        let head = &self.packets[0];
+        // Volatile, the status words are written by the DCP
+        let queued = unsafe { core::ptr::read_volatile(&head.status.queued) };
+        match queued {
+            0 => (),
+            WAITING => return TaskStatus::Idle,
+            _ => return TaskStatus::Done,
+        }
+        // The chain stops on the first failing packet
+        for packet in unsafe { self.packets[0].chain() } {
+            (self.sync_status)(packet);
+            let bits = unsafe { core::ptr::read_volatile(&packet.status.bits) };
+            if bits & 1 == 0 {
+                return TaskStatus::Running;
+            }
+            if bits & 0b1111110 != 0 {
+                break;
+            }
+        }
+        TaskStatus::Done
+    }
+
+    /// Checks if the task was handed to the DCP.
+    pub fn started(&self) -> bool {
+        self.state() != TaskStatus::Idle
+    }
+
+    /// Number of the channel running the task.
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    /// The packet reporting the completion of the whole task.
+    fn last(&self) -> &ControlPacket<'a> {
+        // The packets are borrowed by the task, so the chain is alive
+        unsafe { self.packets[0].chain() }.last().unwrap()
+    }
+
     pub fn poll(&self) -> crate::Result {
-        self.packet.status.poll()
+        match self.poll_chain() {
+            Ok(()) => Ok(self.last().status.tag),
+            Err(nb::Error::Other((_, e))) => Err(nb::Error::Other(e)),
+            Err(nb::Error::WouldBlock) => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    /// Blocks until the task completes and returns its result.
+    ///
+    /// Consumes the task, so the packets and their buffers are free to use once it returns.
+    pub fn wait(self) -> core::result::Result<Tag, crate::Error> {
+        nb::block!(self.poll())
+    }
+
+    /// Polls the packets in execution order.
+    ///
+    /// The chain stops on the first failing packet, whose position is returned with the error.
+    fn poll_chain(&self) -> nb::Result<(), (usize, crate::Error)> {
+        if let Some(queue) = self.queue {
+            // A failure is recorded in the packets dropped from the queue
+            let _ = queue.advance();
+            let queued = unsafe { core::ptr::read_volatile(&self.packets[0].status.queued) };
+            if queued >= ABANDONED {
+                // The DCP never saw the packets, there's nothing to finish
+                self.finished.set(true);
+                let err = crate::Error::Executor(ExError::from_abandoned(queued));
+                return Err(nb::Error::Other((0, err)));
+            }
+        }
+        let mut res = Ok(());
+        for (i, packet) in unsafe { self.packets[0].chain() }.enumerate() {
+            // The status word is written by the DCP, don't read a stale cached copy
+            if !self.finished.get() {
+                (self.sync_status)(packet);
+            }
+            match packet.status.poll() {
+                Ok(_) => continue,
+                Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+                Err(nb::Error::Other(e)) => {
+                    res = Err(nb::Error::Other((i, e)));
+                    break;
+                }
+            }
+        }
+        if !self.finished.replace(true) {
+            unsafe { self.packets[0].chain() }.for_each(|packet| (self.finish)(packet));
+        }
+        res
+    }
+
+    /// Returns the digest calculated by a hashing task, once it has completed successfully.
+    ///
+    /// The digest is read from the payload of the last packet, which must terminate a hash
+    /// computed with `H`, and converted to the standard byte order.
+    pub fn digest<H: HashSelect>(&self) -> Option<H::Output> {
+        self.poll().ok()?;
+        let last = self.last();
+        debug_assert!(last.hash() == H::HASH, "digest read with the wrong algorithm");
+        last.hash_output().map(H::output)
+    }
+
+    /// Same as [`digest`](Self::digest), but returns the digest as the DCP wrote it.
+    ///
+    /// The DCP has no option to swap the digest, [`digest`](Self::digest) puts it in the standard
+    /// byte order in software. The SHA digests are byte-reversed in this layout, which is the one
+    /// expected in the payload of a packet with
+    /// [`hash_check`](crate::packet::builder::PacketBuilder::hash_check) set.
+    pub fn raw_digest<H: HashSelect>(&self) -> Option<H::Payload> {
+        self.poll().ok()?;
+        let last = self.last();
+        debug_assert!(last.hash() == H::HASH, "digest read with the wrong algorithm");
+        let raw = last.hash_output()?;
+        let mut out = H::Payload::default();
+        out.as_mut().copy_from_slice(raw);
+        Some(out)
+    }
+}
+
+/// A chain of packets that reports which one failed.
+///
+/// Returned by [`Executor::exec_batch`]. Like [`Task`], dropping it waits for completion.
+pub struct BatchTask<'a> {
+    task: Task<'a>,
+}
+
+impl<'a> BatchTask<'a> {
+    /// Polls for completion of the whole chain.
+    ///
+    /// The DCP stops on the first failing packet, its index in the chain is returned with the
+    /// error.
+    pub fn poll(&self) -> nb::Result<(), (usize, crate::Error)> {
+        self.task.poll_chain()
+    }
+
+    /// Number of the channel running the chain.
+    pub fn channel(&self) -> u8 {
+        self.task.channel()
     }
 }
 
@@ -183,3 +1225,213 @@ impl Drop for Task<'_> {
         let _ = nb::block!(self.poll());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock::MockExecutor,
+        ops::{Crc32, Memcopy, Sha256},
+        packet::{builder::PacketBuilder, Hash},
+    };
+
+    /// SHA-256 of `abc`, in the standard byte order.
+    const ABC_SHA256: [u8; 32] = [
+        0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22,
+        0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00,
+        0x15, 0xad,
+    ];
+
+    fn memcopy<'a>(src: &'a [u8], dst: &'a mut [u8]) -> ControlPacket<'a> {
+        PacketBuilder::<Memcopy>::new()
+            .buffers(src, dst)
+            .unwrap()
+            .decr_semaphore()
+            .into()
+    }
+
+    #[test]
+    fn queued_tasks_dropped_out_of_order() {
+        let (src1, mut dst1) = ([1u8; 16], [0u8; 16]);
+        let (src2, mut dst2) = ([2u8; 16], [0u8; 16]);
+        let mut first = memcopy(&src1, &mut dst1);
+        let mut second = memcopy(&src2, &mut dst2);
+
+        let mock = MockExecutor::new();
+        mock.set_emulate(true);
+        mock.set_full(true);
+        let queue = Queued::<_, 2>::new(mock);
+        let first = queue.exec_one(&mut first).unwrap();
+        let second = queue.exec_one(&mut second).unwrap();
+        assert_eq!(queue.queued(), 2);
+        assert_eq!(first.state(), TaskStatus::Idle);
+
+        queue.inner.set_full(false);
+        // Polling the second task starts the first one, then the second one
+        drop(second);
+        assert_eq!(queue.queued(), 0);
+        assert!(first.started());
+        drop(first);
+        assert_eq!(queue.inner.captured().len(), 2);
+        assert_eq!((dst1, dst2), ([1; 16], [2; 16]));
+    }
+
+    #[test]
+    fn queued_packet_waits_for_the_channel() {
+        let src = [3u8; 16];
+        let mut dst = [0u8; 16];
+        let mut packet = memcopy(&src, &mut dst);
+
+        let mock = MockExecutor::new();
+        mock.set_full(true);
+        let queue = Queued::<_, 1>::new(mock);
+        let task = queue.exec_one(&mut packet).unwrap();
+        assert!(matches!(task.poll(), Err(nb::Error::WouldBlock)));
+        assert_eq!(queue.len(), 1);
+
+        queue.inner.set_full(false);
+        assert!(task.poll().is_ok());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn unclocked_queue_drops_its_packets() {
+        let (src1, mut dst1) = ([1u8; 16], [0u8; 16]);
+        let (src2, mut dst2) = ([2u8; 16], [0u8; 16]);
+        let mut first = memcopy(&src1, &mut dst1);
+        let mut second = memcopy(&src2, &mut dst2);
+
+        let mock = MockExecutor::new();
+        mock.set_full(true);
+        let queue = Queued::<_, 2>::new(mock);
+        let first = queue.exec_one(&mut first).unwrap();
+        let second = queue.exec_one(&mut second).unwrap();
+
+        queue.inner.set_full(false);
+        queue.inner.set_clocked(false);
+        assert_eq!(queue.advance(), Err(ExError::NotClocked));
+        assert_eq!(queue.queued(), 0);
+        assert_eq!(first.state(), TaskStatus::Done);
+        assert!(matches!(
+            second.wait(),
+            Err(crate::Error::Executor(ExError::NotClocked))
+        ));
+        drop(first);
+        assert!(queue.inner.captured().is_empty());
+    }
+
+    #[test]
+    fn unclocked_queue_drops_without_hanging() {
+        let src = [1u8; 16];
+        let mut dst = [0u8; 16];
+        let mut packet = memcopy(&src, &mut dst);
+
+        let mock = MockExecutor::new();
+        mock.set_full(true);
+        let queue = Queued::<_, 1>::new(mock);
+        // Without a task borrowing the queue, so it can be dropped with the packet queued
+        unsafe { queue.inner_exec(&mut packet) }.unwrap();
+        queue.inner.set_full(false);
+        queue.inner.set_clocked(false);
+        drop(queue);
+        assert_eq!(packet.status.queued, ExError::NotClocked.abandoned());
+    }
+
+    std::thread_local! {
+        static SYNCED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Counts the calls to its status hook.
+    struct Syncing(MockExecutor);
+
+    impl Executor for Syncing {
+        fn sync_status(_task: &ControlPacket) {
+            SYNCED.with(|n| n.set(n.get() + 1));
+        }
+
+        unsafe fn inner_exec(&self, task: &mut ControlPacket) -> Result<u8, ExError> {
+            self.0.inner_exec(task)
+        }
+    }
+
+    #[test]
+    fn task_reads_the_status_through_the_executor_hook() {
+        let (src, mut dst) = ([1u8; 16], [0u8; 16]);
+        let mut packet = memcopy(&src, &mut dst);
+        let ex = Syncing(MockExecutor::new());
+        let task = ex.exec_one(&mut packet).unwrap();
+        assert_eq!(task.state(), TaskStatus::Done);
+        assert_eq!(SYNCED.with(Cell::get), 1);
+        task.wait().unwrap();
+        assert_eq!(SYNCED.with(Cell::get), 2);
+    }
+
+    #[test]
+    fn digest_layouts() {
+        // The mock doesn't run SHA-256, write the digest where and how the DCP does
+        let mut payload = ABC_SHA256;
+        payload.reverse();
+        let raw = payload;
+        let mut packet: ControlPacket = PacketBuilder::<Hash>::new()
+            .hash(Hash::Sha256)
+            .hash_init()
+            .hash_term()
+            .input(b"abc")
+            .payload(&mut payload)
+            .decr_semaphore()
+            .into();
+
+        let mock = MockExecutor::new();
+        let task = mock.exec_one(&mut packet).unwrap();
+        assert_eq!(task.digest::<Sha256>(), Some(ABC_SHA256));
+        assert_eq!(task.raw_digest::<Sha256>(), Some(raw));
+    }
+
+    #[test]
+    fn crc32_digest_layouts() {
+        let mut payload = [0; 4];
+        let mut packet: ControlPacket = PacketBuilder::<Hash>::new()
+            .hash(Hash::Crc32)
+            .hash_init()
+            .hash_term()
+            .input(b"123456789")
+            .payload(&mut payload)
+            .decr_semaphore()
+            .into();
+
+        let mock = MockExecutor::new();
+        mock.set_emulate(true);
+        let task = mock.exec_one(&mut packet).unwrap();
+        assert_eq!(task.digest::<Crc32>(), Some(0x0376_E6E7));
+        assert_eq!(task.raw_digest::<Crc32>(), Some(0x0376_E6E7u32.to_le_bytes()));
+    }
+
+    /// Picks channels like `Scheduler::inner_exec`, marking each as busy.
+    fn submit(cursor: &mut u8, free: &mut ChannelMask) -> Option<u8> {
+        let index = next_free(*cursor, *free)?;
+        *cursor = (index + 1) % CHANNELS.len() as u8;
+        free.0 &= !(1 << index);
+        Some(index)
+    }
+
+    #[test]
+    fn round_robin_spreads_tasks_over_the_channels() {
+        let (mut cursor, mut free) = (0, ChannelMask::ALL);
+        let picked: std::vec::Vec<_> = (0..5).map(|_| submit(&mut cursor, &mut free)).collect();
+        assert_eq!(picked, [Some(0), Some(1), Some(2), Some(3), None]);
+
+        // Channel 1 completes, it's found by wrapping around
+        free.0 |= 1 << 1;
+        assert_eq!(submit(&mut cursor, &mut free), Some(1));
+        assert_eq!(cursor, 2);
+    }
+
+    #[test]
+    fn round_robin_skips_unused_and_busy_channels() {
+        let free = ChannelMask(1 << 0 | 1 << 2);
+        assert_eq!(next_free(0, free), Some(0));
+        assert_eq!(next_free(1, free), Some(2));
+        assert_eq!(next_free(3, free), Some(0));
+        assert_eq!(next_free(0, ChannelMask(0)), None);
+    }
+}
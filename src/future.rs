@@ -0,0 +1,87 @@
+//! Interrupt driven futures for DCP tasks.
+//!
+//! [`AsyncTask`]s park on the DCP interrupt instead of spinning: call [`on_interrupt`] from the
+//! `DCP` interrupt handler to wake them up. Without the interrupt, [`poll_async`] sleeps on a timer
+//! between polls.
+
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use atomic_waker::AtomicWaker;
+use embedded_hal_async::delay::DelayNs;
+use imxrt_ral::dcp;
+
+use crate::{ex::Task, Error, Tag};
+
+/// Wakers of the tasks waiting on each channel.
+static WAKERS: [AtomicWaker; 4] = [
+    AtomicWaker::new(),
+    AtomicWaker::new(),
+    AtomicWaker::new(),
+    AtomicWaker::new(),
+];
+
+/// A [`Task`] that can be awaited.
+///
+/// The packet has its interrupt flag set on submission, so it completes by firing the DCP
+/// interrupt.
+pub struct AsyncTask<'a> {
+    task: Task<'a>,
+    channel: usize,
+}
+
+impl<'a> AsyncTask<'a> {
+    pub(crate) fn new(task: Task<'a>, channel: usize) -> Self {
+        Self { task, channel }
+    }
+}
+
+impl Future for AsyncTask<'_> {
+    type Output = core::result::Result<Tag, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        WAKERS[self.channel].register(cx.waker());
+        match self.task.poll() {
+            Ok(tag) => Poll::Ready(Ok(tag)),
+            Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+            Err(nb::Error::WouldBlock) => Poll::Pending,
+        }
+    }
+}
+
+/// Services the DCP interrupt.
+///
+/// Clears the pending channel interrupts and wakes the tasks waiting on them.
+/// Call this from the `DCP` interrupt handler.
+pub fn on_interrupt() {
+    // Also records the channels for Scheduler::drain_completed
+    let irq = crate::dcp::service_irq(unsafe { &*dcp::DCP });
+
+    for (ch, waker) in WAKERS.iter().enumerate() {
+        if irq.0 & (1 << ch) != 0 {
+            waker.wake();
+        }
+    }
+}
+
+/// Interval between two polls in [`poll_async`].
+pub const POLL_INTERVAL_US: u32 = 10;
+
+/// Waits for a task to complete, sleeping [`POLL_INTERVAL_US`] between polls.
+///
+/// Doesn't need the DCP interrupt, the other async tasks can run while `timer` sleeps.
+pub async fn poll_async<T: DelayNs>(
+    task: &Task<'_>,
+    timer: &mut T,
+) -> core::result::Result<Tag, Error> {
+    loop {
+        match task.poll() {
+            Ok(tag) => return Ok(tag),
+            Err(nb::Error::Other(e)) => return Err(e),
+            Err(nb::Error::WouldBlock) => timer.delay_us(POLL_INTERVAL_US).await,
+        }
+    }
+}
@@ -0,0 +1,553 @@
+//! High level hashing on top of an [`Executor`].
+//!
+//! The hashers feed the data to the DCP one packet at a time, the running hash is kept in the
+//! channel context between packets. Use them with a single channel executor, packets landing on
+//! different channels would not share the hash state. A hash spanning several packets fails
+//! with [`MultiChannel`](crate::ex::ExError::MultiChannel) on executors without a single
+//! [`channel`](Executor::channel), like a [`Scheduler`](crate::ex::Scheduler) using more than
+//! one.
+
+use core::{cell::Cell, marker::PhantomData};
+
+use crate::{
+    buffer::DmaBuffer,
+    ex::{ExError, Executor},
+    ops::{Crc32, HashSelect, MemcopyHash, Sha1, Sha256},
+    packet::{
+        builder::check_lengths,
+        ControlPacket, Hash,
+    },
+    prelude::PacketBuilder,
+    verify::digest_eq,
+    Error,
+};
+
+/// State of a channel saved by the DCP while a hash is in progress.
+///
+/// Lets a hash be suspended to run other work on the channel, and resumed later with
+/// [`Scheduler::save_context`](crate::ex::Scheduler::save_context) and
+/// [`restore_context`](crate::ex::Scheduler::restore_context). The 52 bytes hold the running
+/// hash (or CRC) and the cipher state of the channel, their layout is not documented by NXP, so
+/// treat them as opaque and only give them back to the same hash on the same device.
+#[derive(Clone)]
+pub struct HashContext(pub(crate) [u8; 52]);
+
+impl HashContext {
+    pub(crate) const fn new() -> Self {
+        Self([0; 52])
+    }
+
+    /// The raw context, e.g. to store it.
+    pub fn as_bytes(&self) -> &[u8; 52] {
+        &self.0
+    }
+
+    /// Reads back a context returned by [`as_bytes`](Self::as_bytes).
+    pub fn from_bytes(bytes: [u8; 52]) -> Self {
+        Self(bytes)
+    }
+}
+
+/// Hashes `data` and blocks until the DCP is done.
+///
+/// Initializes the hash if `init` is set, terminates it writing the digest to the payload if one
+/// is passed.
+///
+/// Returns [`MultiChannel`](ExError::MultiChannel) if the hash spans several packets and `ex`
+/// may run them on different channels.
+fn run<E: Executor>(
+    ex: &E,
+    hash: Hash,
+    data: &[u8],
+    init: bool,
+    payload: Option<&mut [u8]>,
+) -> Result<(), Error> {
+    if !(init && payload.is_some()) && ex.channel().is_none() {
+        return Err(Error::Executor(ExError::MultiChannel));
+    }
+    let mut builder = PacketBuilder::<Hash>::new()
+        .hash(hash)
+        .input(data)
+        .decr_semaphore();
+    if init {
+        builder = builder.hash_init();
+    }
+    if let Some(payload) = payload {
+        builder = builder.hash_term().payload(payload);
+    }
+    let mut packet: ControlPacket = builder.into();
+    let task = ex.exec_one(&mut packet).map_err(Error::Executor)?;
+    nb::block!(task.poll())?;
+    Ok(())
+}
+
+/// Hashes `data` in a single packet and returns the digest.
+pub fn digest<H: HashSelect, E: Executor>(ex: &E, data: &[u8]) -> Result<H::Output, Error> {
+    let mut payload = H::Payload::default();
+    run(ex, H::HASH, data, true, Some(payload.as_mut()))?;
+    Ok(H::output(payload.as_ref()))
+}
+
+/// Software implementation of the hash `H`, for chips whose DCP doesn't support it.
+///
+/// Implemented by closures taking the data and returning its digest.
+///
+/// The fallback hashes the whole input at once, so only the one-shot functions use it:
+/// [`digest_with_fallback`] and [`verify_hash_with_fallback`]. [`Hasher`], [`StreamingHash`]
+/// and the tasks of hand-built packets never hold the whole input, they return the
+/// [`SetupError`](Error::SetupError) instead.
+pub trait Fallback<H: HashSelect> {
+    /// Hashes `data`.
+    fn digest(&self, data: &[u8]) -> H::Output;
+}
+
+impl<H: HashSelect, F: Fn(&[u8]) -> H::Output> Fallback<H> for F {
+    fn digest(&self, data: &[u8]) -> H::Output {
+        self(data)
+    }
+}
+
+/// Same as [`digest()`], but hashes `data` with `fallback` if the DCP rejects the operation with a
+/// setup error, like it does for hashes it doesn't implement.
+///
+/// Other errors are returned, use [`digest()`] to never fall back.
+pub fn digest_with_fallback<H, E, F>(ex: &E, data: &[u8], fallback: &F) -> Result<H::Output, Error>
+where
+    H: HashSelect,
+    E: Executor,
+    F: Fallback<H>,
+{
+    match digest::<H, E>(ex, data) {
+        Err(Error::SetupError(_)) => Ok(fallback.digest(data)),
+        res => res,
+    }
+}
+
+/// Same as [`verify_hash`], but hashes `data` with `fallback` and compares the digests in
+/// software if the DCP rejects the operation with a setup error.
+///
+/// The software comparison is done in constant time, see [`digest_eq`].
+pub fn verify_hash_with_fallback<H, E, F>(
+    ex: &E,
+    data: &[u8],
+    expected: &H::Output,
+    fallback: &F,
+) -> Result<bool, Error>
+where
+    H: HashSelect,
+    E: Executor,
+    F: Fallback<H>,
+{
+    match verify_hash::<H, E>(ex, data, expected) {
+        Err(Error::SetupError(_)) => Ok(digest_eq::<H>(&fallback.digest(data), expected)),
+        res => res,
+    }
+}
+
+/// Hashes `data` and checks the digest against `expected`.
+///
+/// The comparison is done by the DCP. Returns `Ok(false)` if the digests don't match, errors are
+/// only returned for failures of the operation itself.
+pub fn verify_hash<H: HashSelect, E: Executor>(
+    ex: &E,
+    data: &[u8],
+    expected: &H::Output,
+) -> Result<bool, Error> {
+    let mut payload = H::raw(expected);
+    let mut packet: ControlPacket = PacketBuilder::<Hash>::new()
+        .hash(H::HASH)
+        .hash_init()
+        .hash_term()
+        .hash_check()
+        .input(data)
+        .payload(payload.as_mut())
+        .decr_semaphore()
+        .into();
+    let task = ex.exec_one(&mut packet).map_err(Error::Executor)?;
+    match nb::block!(task.poll()) {
+        Ok(_) => Ok(true),
+        Err(Error::HashMismatch(_)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Copies `src` to `dst` and returns the digest of the copied data.
+///
+/// Both happen in a single memcopy and hash packet, e.g. to load a firmware image into RAM and
+/// verify it.
+///
+/// Returns a [`BufferError`](crate::packet::builder::BufferError) if `src` and `dst` have
+/// different lengths.
+pub fn copy_digest<H: HashSelect, E: Executor>(
+    ex: &E,
+    src: &[u8],
+    dst: &mut [u8],
+) -> Result<H::Output, Error> {
+    check_lengths(src, dst).map_err(Error::Buffer)?;
+    let mut payload = H::Payload::default();
+    let mut packet: ControlPacket = PacketBuilder::<MemcopyHash>::new()
+        .hash(H::HASH)
+        .hash_init()
+        .hash_term()
+        .buffers_unchecked(src, dst)
+        .payload(payload.as_mut())
+        .decr_semaphore()
+        .into();
+    let task = ex.exec_one(&mut packet).map_err(Error::Executor)?;
+    nb::block!(task.poll())?;
+    // Releases the borrow on the payload
+    drop(task);
+    Ok(H::output(payload.as_ref()))
+}
+
+/// Hashes data spread over multiple buffers without copying it.
+///
+/// Every buffer passed to [`update`](Self::update) is hashed by its own packet. A buffer is only
+/// submitted once the next one comes in (or on [`finalize`](Self::finalize)), so the hash can be
+/// initialized by the first packet and terminated by the last one.
+pub struct StreamingHash<'a, 'e, H: HashSelect, E: Executor> {
+    ex: &'e E,
+    pending: Option<&'a [u8]>,
+    started: bool,
+    _hash: PhantomData<H>,
+}
+
+impl<'a, 'e, H: HashSelect, E: Executor> StreamingHash<'a, 'e, H, E> {
+    /// Starts a new hash.
+    pub fn init(ex: &'e E) -> Self {
+        Self {
+            ex,
+            pending: None,
+            started: false,
+            _hash: PhantomData,
+        }
+    }
+
+    fn run(&mut self, data: &[u8], payload: Option<&mut [u8]>) -> Result<(), Error> {
+        run(self.ex, H::HASH, data, !self.started, payload)?;
+        self.started = true;
+        Ok(())
+    }
+
+    /// Adds a buffer to the hash.
+    ///
+    /// Hashes the buffer passed to the previous call, which must be a multiple of
+    /// [`H::BLOCK_BYTES`](HashSelect::BLOCK_BYTES) long.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the previous buffer is not a multiple of the block size.
+    pub fn update(&mut self, block: &'a [u8]) -> Result<(), Error> {
+        if let Some(prev) = self.pending.replace(block) {
+            assert!(
+                prev.len() % H::BLOCK_BYTES == 0,
+                "intermediate hash blocks must be a multiple of {} bytes",
+                H::BLOCK_BYTES
+            );
+            self.run(prev, None)?;
+        }
+        Ok(())
+    }
+
+    /// Hashes the last buffer and returns the digest.
+    pub fn finalize(mut self) -> Result<H::Output, Error> {
+        let mut payload = H::Payload::default();
+        let last = self.pending.take().unwrap_or(&[]);
+        self.run(last, Some(payload.as_mut()))?;
+        Ok(H::output(payload.as_ref()))
+    }
+}
+
+/// Incremental hasher.
+///
+/// Buffers the input in 64 byte blocks and hashes them on the DCP as they fill up.
+/// SHA digests are returned in the standard byte order, the DCP writes them reversed.
+pub struct Hasher<'e, H: HashSelect, E: Executor> {
+    ex: &'e E,
+    /// Input not hashed yet, always holds the last bytes to hash before finalizing.
+    block: DmaBuffer<64>,
+    len: usize,
+    started: Cell<bool>,
+    _hash: PhantomData<H>,
+}
+
+/// SHA-1 hasher.
+pub type Sha1Hasher<'e, E> = Hasher<'e, Sha1, E>;
+/// SHA-256 hasher.
+///
+/// With the `digest` feature it implements the `Update`, `FixedOutput` and `HashMarker` traits of
+/// the `digest` crate. It borrows an executor, so it can't implement `Default` and the blanket
+/// `Digest` implementation doesn't apply: create it with [`Hasher::new`] and use the methods of
+/// the other traits, like `FixedOutput::finalize_fixed`.
+pub type Sha256Hasher<'e, E> = Hasher<'e, Sha256, E>;
+/// Incremental CRC32, see [`Crc32`] for the exact variant.
+///
+/// The checksum is returned as a native `u32`, the value computed by `crc::CRC_32_MPEG_2`.
+pub type Crc32Digest<'e, E> = Hasher<'e, Crc32, E>;
+
+impl<'e, H: HashSelect, E: Executor> Hasher<'e, H, E> {
+    pub fn new(ex: &'e E) -> Self {
+        Self {
+            ex,
+            block: DmaBuffer::new(),
+            len: 0,
+            started: Cell::new(false),
+            _hash: PhantomData,
+        }
+    }
+
+    fn run(&self, data: &[u8], payload: Option<&mut [u8]>) -> Result<(), Error> {
+        run(self.ex, H::HASH, data, !self.started.get(), payload)?;
+        self.started.set(true);
+        Ok(())
+    }
+
+    /// Hashes `data`.
+    ///
+    /// Whole blocks are hashed straight from `data`, the rest is buffered.
+    pub fn update(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        if self.len > 0 && self.len + data.len() > 64 {
+            let (head, tail) = data.split_at(64 - self.len);
+            self.block[self.len..].copy_from_slice(head);
+            self.run(&self.block, None)?;
+            self.len = 0;
+            data = tail;
+        }
+        if self.len == 0 && !data.is_empty() {
+            // Keep at least one byte for the final packet
+            let whole = (data.len() - 1) & !63;
+            if whole > 0 {
+                self.run(&data[..whole], None)?;
+                data = &data[whole..];
+            }
+        }
+        self.block[self.len..self.len + data.len()].copy_from_slice(data);
+        self.len += data.len();
+        Ok(())
+    }
+
+    /// Hashes the buffered data and returns the digest.
+    pub fn finalize(self) -> Result<H::Output, Error> {
+        let mut payload = H::Payload::default();
+        self.run(&self.block[..self.len], Some(payload.as_mut()))?;
+        Ok(H::output(payload.as_ref()))
+    }
+}
+
+impl<'e, E: Executor> Crc32Digest<'e, E> {
+    /// Resumes a CRC from `seed`, the checksum of the data before.
+    ///
+    /// Hashing `b` from the checksum of `a` gives the checksum of `a` followed by `b`, like
+    /// hashing both with a single digest.
+    ///
+    /// The checksum has no final XOR, so it is the value of the CRC register itself, but the
+    /// DCP has no way to load the register. The digest starts by hashing four bytes chosen to
+    /// bring the register from its initial value to `seed`.
+    pub fn continue_from(ex: &'e E, seed: u32) -> Self {
+        let mut crc = Self::new(ex);
+        crc.block[..4].copy_from_slice(&crc32_preimage(seed));
+        crc.len = 4;
+        crc
+    }
+}
+
+/// Four bytes taking the CRC register from `0xFFFFFFFF` to `crc`.
+///
+/// Hashing a 32 bit word XORs it into the register, then shifts the register 32 times, reducing
+/// it by the polynomial. The shifts are undone one at a time, a set low bit means the polynomial
+/// was XORed in.
+fn crc32_preimage(mut crc: u32) -> [u8; 4] {
+    const POLY: u32 = 0x04C1_1DB7;
+    for _ in 0..32 {
+        crc = if crc & 1 != 0 {
+            (crc ^ POLY) >> 1 | 0x8000_0000
+        } else {
+            crc >> 1
+        };
+    }
+    (crc ^ 0xFFFF_FFFF).to_be_bytes()
+}
+
+#[cfg(feature = "digest")]
+mod digest_impl {
+    use super::Sha256Hasher;
+    use crate::ex::Executor;
+    use digest::{consts::U32, FixedOutput, HashMarker, Output, OutputSizeUser, Update};
+
+    impl<E: Executor> OutputSizeUser for Sha256Hasher<'_, E> {
+        type OutputSize = U32;
+    }
+
+    impl<E: Executor> Update for Sha256Hasher<'_, E> {
+        /// # Panics
+        ///
+        /// Panics if the DCP reports an error.
+        fn update(&mut self, data: &[u8]) {
+            Sha256Hasher::update(self, data).expect("DCP hashing failed")
+        }
+    }
+
+    impl<E: Executor> FixedOutput for Sha256Hasher<'_, E> {
+        /// # Panics
+        ///
+        /// Panics if the DCP reports an error.
+        fn finalize_into(self, out: &mut Output<Self>) {
+            let digest = Sha256Hasher::finalize(self).expect("DCP hashing failed");
+            out.copy_from_slice(&digest);
+        }
+    }
+
+    impl<E: Executor> HashMarker for Sha256Hasher<'_, E> {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{crc32_mpeg2, MockExecutor};
+
+    /// Runs the packets on the mock, without promising a single channel like a `Scheduler`.
+    struct AnyChannel(MockExecutor);
+
+    impl Executor for AnyChannel {
+        unsafe fn inner_exec(&self, task: &mut ControlPacket) -> Result<u8, ExError> {
+            self.0.inner_exec(task)
+        }
+    }
+
+    #[test]
+    fn multi_packet_hash_needs_a_single_channel() {
+        let ex = AnyChannel(MockExecutor::new());
+        ex.0.set_emulate(true);
+        let mut crc = Crc32Digest::new(&ex);
+        let res = crc.update(&[0; 200]);
+        assert!(matches!(res, Err(Error::Executor(ExError::MultiChannel))));
+        assert!(ex.0.captured().is_empty());
+    }
+
+    #[test]
+    fn single_packet_hash_runs_anywhere() {
+        let ex = AnyChannel(MockExecutor::new());
+        ex.0.set_emulate(true);
+        let mut crc = Crc32Digest::new(&ex);
+        crc.update(b"123456789").unwrap();
+        assert_eq!(crc.finalize().unwrap(), 0x0376_E6E7);
+        assert_eq!(digest::<Crc32, _>(&ex, b"123456789").unwrap(), 0x0376_E6E7);
+    }
+
+    /// Test pattern of `len` bytes.
+    fn pattern(len: u32, mul: u32, add: u32) -> std::vec::Vec<u8> {
+        (0..len).map(|i| (i * mul + add) as u8).collect()
+    }
+
+    // CRC-32/MPEG-2 vectors computed with zlib's CRC-32, its bit-reflected form
+
+    #[test]
+    fn crc32_updates_match_the_whole_input() {
+        let ex = MockExecutor::new();
+        ex.set_emulate(true);
+        let data = pattern(300, 7, 3);
+
+        let mut crc = Crc32Digest::new(&ex);
+        for chunk in [&data[..1], &data[1..70], &data[70..71], &data[71..]] {
+            crc.update(chunk).unwrap();
+        }
+        assert_eq!(crc.finalize().unwrap(), 0x21D2_C248);
+
+        let fox = b"The quick brown fox jumps over the lazy dog";
+        let mut crc = Crc32Digest::new(&ex);
+        for chunk in fox.chunks(10) {
+            crc.update(chunk).unwrap();
+        }
+        assert_eq!(crc.finalize().unwrap(), 0xBA62_119E);
+    }
+
+    #[test]
+    fn continue_from_matches_the_whole_input() {
+        let ex = MockExecutor::new();
+        ex.set_emulate(true);
+        let data = pattern(200, 13, 5);
+        let (header, body) = data.split_at(37);
+
+        let mut crc = Crc32Digest::new(&ex);
+        crc.update(header).unwrap();
+        let seed = crc.finalize().unwrap();
+
+        let mut crc = Crc32Digest::continue_from(&ex, seed);
+        crc.update(body).unwrap();
+        assert_eq!(crc.finalize().unwrap(), 0xD4A5_ABF2);
+
+        let seed = digest::<Crc32, _>(&ex, b"12345").unwrap();
+        let mut crc = Crc32Digest::continue_from(&ex, seed);
+        crc.update(b"6789").unwrap();
+        assert_eq!(crc.finalize().unwrap(), 0x0376_E6E7);
+
+        let crc = Crc32Digest::continue_from(&ex, seed);
+        assert_eq!(crc.finalize().unwrap(), seed);
+    }
+
+    #[test]
+    fn crc32_preimage_reaches_the_seed() {
+        for seed in [0, 1, 0xFFFF_FFFF, 0x0376_E6E7, 0x8000_0000] {
+            assert_eq!(crc32_mpeg2(0xFFFF_FFFF, &crc32_preimage(seed)), seed);
+        }
+    }
+
+    #[test]
+    fn sha_digests_match_sha256sum() {
+        // Payload layout of the DCP, the digests of "abc" byte-reversed
+        const ABC_SHA1_RAW: [u8; 20] = [
+            0x9d, 0xd8, 0xd0, 0x9c, 0x6c, 0xc2, 0x50, 0x78, 0x71, 0x25, 0x3e, 0xba, 0x6a, 0x81,
+            0x06, 0x47, 0x36, 0x3e, 0x99, 0xa9,
+        ];
+        const ABC_SHA256_RAW: [u8; 32] = [
+            0xad, 0x15, 0x00, 0xf2, 0x61, 0xff, 0x10, 0xb4, 0x9c, 0x7a, 0x17, 0x96, 0xa3, 0x61,
+            0x03, 0xb0, 0x23, 0x22, 0xae, 0x5d, 0xde, 0x40, 0x41, 0x41, 0xea, 0xcf, 0x01, 0x8f,
+            0xbf, 0x16, 0x78, 0xba,
+        ];
+        let ex = MockExecutor::new();
+
+        ex.set_hash_output(&ABC_SHA1_RAW);
+        let sha1 = digest::<Sha1, _>(&ex, b"abc").unwrap();
+        assert_eq!(hex(&sha1), "a9993e364706816aba3e25717850c26c9cd0d89d");
+
+        ex.set_hash_output(&ABC_SHA256_RAW);
+        let sha256 = digest::<Sha256, _>(&ex, b"abc").unwrap();
+        assert_eq!(
+            hex(&sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    /// Formats a digest like sha256sum.
+    fn hex(digest: &[u8]) -> std::string::String {
+        digest.iter().map(|b| std::format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn one_shot_hashes_fall_back_on_setup_errors() {
+        // The emulating mock rejects SHA packets with a setup error
+        let ex = MockExecutor::new();
+        ex.set_emulate(true);
+        let fallback = |data: &[u8]| [data.len() as u8; 32];
+
+        let res = digest_with_fallback::<Sha256, _, _>(&ex, b"abc", &fallback);
+        assert_eq!(res.unwrap(), [3; 32]);
+        let res = verify_hash_with_fallback::<Sha256, _, _>(&ex, b"abc", &[3; 32], &fallback);
+        assert!(res.unwrap());
+        let res = verify_hash_with_fallback::<Sha256, _, _>(&ex, b"abc", &[4; 32], &fallback);
+        assert!(!res.unwrap());
+
+        let mut hasher = Sha256Hasher::new(&ex);
+        hasher.update(b"abc").unwrap();
+        assert!(matches!(hasher.finalize(), Err(Error::SetupError(_))));
+    }
+
+    #[test]
+    fn fallback_is_skipped_when_the_dcp_hashes() {
+        let ex = MockExecutor::new();
+        ex.set_emulate(true);
+        let fallback = |_: &[u8]| 0;
+        let res = digest_with_fallback::<Crc32, _, _>(&ex, b"123456789", &fallback);
+        assert_eq!(res.unwrap(), 0x0376_E6E7);
+    }
+}
@@ -1,36 +1,192 @@
 #![no_std]
 
+#[cfg(any(test, feature = "std"))]
+extern crate std;
+
 use imxrt_ral as ral;
 pub use nb::block;
 
+#[cfg(feature = "cipher")]
+pub mod aes;
+#[cfg(feature = "cortex-m")]
+mod cache;
+pub mod blocking;
+pub mod buffer;
+pub mod cbc;
 pub mod channels;
 pub mod dcp;
 pub mod ex;
+#[cfg(feature = "async")]
+pub mod future;
+pub mod hash;
+#[cfg(any(test, feature = "std"))]
+pub mod mock;
 pub mod ops;
 pub mod packet;
+pub mod verify;
 
 /// Derived from the DCP status field when an operation fails.
-/// Holds the error kind and an 8 bit error code.
-// I haven't been able to find a way to interpret the 8 bit error codes, if anyone finds something
-// useful please submit a PR
+/// Holds the error kind and the decoded error code.
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     Executor(ex::ExError),
-    HashMismatch(u8),
-    SetupError(u8),
-    PacketError(u8),
-    SourceError(u8),
-    DestError(u8),
-    Other(u8)
+    Buffer(packet::builder::BufferError),
+    HashMismatch(DcpErrorCode),
+    SetupError(DcpErrorCode),
+    PacketError(DcpErrorCode),
+    SourceError(DcpErrorCode),
+    DestError(DcpErrorCode),
+    PageFault(DcpErrorCode),
+    Other(DcpErrorCode)
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (msg, code) = match self {
+            Error::Executor(e) => return write!(f, "DCP executor error: {}", e),
+            Error::Buffer(e) => return write!(f, "invalid DCP buffers: {}", e),
+            Error::HashMismatch(code) => ("DCP hash mismatch", code),
+            Error::SetupError(code) => ("DCP setup error", code),
+            Error::PacketError(code) => ("DCP packet bus error", code),
+            Error::SourceError(code) => ("DCP source bus error", code),
+            Error::DestError(code) => ("DCP destination bus error", code),
+            Error::PageFault(code) => ("DCP page fault", code),
+            Error::Other(code) => ("DCP error", code),
+        };
+        write!(f, "{} (code {:#04x})", msg, code.code())
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::Executor(e) => Some(e),
+            Error::Buffer(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Error flags and additional error code reported in the packet status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DcpErrorCode {
+    flags: u8,
+    code: u8,
+}
+
+impl DcpErrorCode {
+    pub(crate) fn new(flags: u8, code: u8) -> Self {
+        Self { flags, code }
+    }
+
+    /// Raw status flags, bit 0 is the completion flag.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Raw 8 bit additional error code.
+    pub fn code(&self) -> u8 {
+        self.code
+    }
+
+    /// The hash calculated by a HashCheck packet didn't match the one in the payload.
+    pub fn hash_mismatch(&self) -> bool {
+        self.flags & 1 << 1 != 0
+    }
+
+    /// Invalid programming configuration, like a buffer length that is not a multiple of the
+    /// natural data size for the operation.
+    pub fn setup_error(&self) -> bool {
+        self.flags & 1 << 2 != 0
+    }
+
+    /// Bus error reading the packet or payload, or writing the status back.
+    pub fn packet_error(&self) -> bool {
+        self.flags & 1 << 3 != 0
+    }
+
+    /// Bus error reading from the source buffer.
+    pub fn bus_error_source(&self) -> bool {
+        self.flags & 1 << 4 != 0
+    }
+
+    /// Bus error writing to the destination buffer.
+    pub fn bus_error_dest(&self) -> bool {
+        self.flags & 1 << 5 != 0
+    }
+
+    /// Page fault while translating a virtual address.
+    pub fn page_fault(&self) -> bool {
+        self.flags & 1 << 6 != 0
+    }
+
+    /// The packet is chained but the next pointer is null.
+    pub fn next_chain_is_zero(&self) -> bool {
+        self.code == 0x01
+    }
+
+    /// The semaphore is non-zero but neither chain bit is set.
+    pub fn no_chain(&self) -> bool {
+        self.code == 0x02
+    }
+
+    /// Error reading or writing the context buffer.
+    pub fn context_error(&self) -> bool {
+        self.code == 0x03
+    }
+
+    /// Error reading or writing the payload.
+    pub fn payload_error(&self) -> bool {
+        self.code == 0x04
+    }
+
+    /// The packet enables an invalid combination of operations (like blit and hash).
+    pub fn invalid_mode(&self) -> bool {
+        self.code == 0x05
+    }
 }
 
 pub type Tag = u8;
 
 pub type Result = nb::Result<Tag, Error>;
 
+/// Error returned by [`block_with_timeout`].
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimeoutOr<E> {
+    /// The deadline passed before the operation completed.
+    Timeout,
+    /// The operation failed.
+    Other(E),
+}
+
+/// Polls until the operation completes or `now()` reaches `deadline`.
+///
+/// `now` must return a monotonic tick count, `deadline` is in the same unit.
+/// A timeout usually means the channel is hung (e.g. on a bad chain pointer), the DCP should be
+/// reset before being used again.
+pub fn block_with_timeout<F: FnMut() -> u64>(
+    mut poll: impl FnMut() -> Result,
+    mut now: F,
+    deadline: u64,
+) -> core::result::Result<Tag, TimeoutOr<Error>> {
+    loop {
+        match poll() {
+            Ok(tag) => return Ok(tag),
+            Err(nb::Error::Other(e)) => return Err(TimeoutOr::Other(e)),
+            Err(nb::Error::WouldBlock) if now() >= deadline => return Err(TimeoutOr::Timeout),
+            Err(nb::Error::WouldBlock) => {}
+        }
+    }
+}
+
 pub mod prelude {
     pub use crate::{
         ex::Executor,
+        blocking,
+        buffer::DmaBuffer,
         channels::*,
         ops,
         packet::builder::PacketBuilder,
@@ -0,0 +1,245 @@
+//! Host-side executor for testing packet construction without the hardware.
+
+use core::{
+    cell::{Cell, RefCell},
+    convert::TryFrom,
+    ptr,
+};
+use std::vec::Vec;
+
+use crate::{
+    ex::{ExError, Executor},
+    packet::{Control0Flag, ControlPacket, Hash},
+};
+
+/// The fields of a [`ControlPacket`] received by a [`MockExecutor`].
+///
+/// Pointers are stored as addresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapturedPacket {
+    pub next: usize,
+    /// Control0 word, flags and tag.
+    pub control0: u32,
+    /// Control1 word, crypto config or blit stride.
+    pub control1: u32,
+    /// Source address, or the fill constant.
+    pub source: usize,
+    pub dest: usize,
+    /// Buffer size, or blit width and height.
+    pub bufsize: u32,
+    pub payload: usize,
+    pub tag: u8,
+}
+
+/// An [`Executor`] that records the packets it receives and completes them right away.
+///
+/// Chained packets are recorded in execution order, tasks are reported as running on channel 0.
+/// Every packet is completed with the status set by [`set_status`](Self::set_status),
+/// successfully by default.
+///
+/// With [`set_emulate`](Self::set_emulate) the executor also carries out the operations the
+/// DCP would: memcopy, constant fill, blit and CRC32 hashing. Packets using the cipher or the SHA
+/// hashes complete with a setup error instead.
+pub struct MockExecutor {
+    captured: RefCell<Vec<CapturedPacket>>,
+    status: Cell<(u8, u8)>,
+    full: Cell<bool>,
+    clocked: Cell<bool>,
+    /// Written to the payload of the packets terminating a hash.
+    hash_output: RefCell<Option<Vec<u8>>>,
+    emulate: Cell<bool>,
+    /// Running CRC, kept between packets like the channel context.
+    crc: Cell<u32>,
+}
+
+/// Status bits of a completed packet with a hash mismatch.
+const HASH_MISMATCH: u8 = 0b11;
+/// Status bits of a completed packet with a setup error.
+const SETUP_ERROR: u8 = 0b101;
+
+impl MockExecutor {
+    pub fn new() -> Self {
+        Self {
+            captured: RefCell::new(Vec::new()),
+            status: Cell::new((1, 0)),
+            full: Cell::new(false),
+            clocked: Cell::new(true),
+            hash_output: RefCell::new(None),
+            emulate: Cell::new(false),
+            crc: Cell::new(0xFFFF_FFFF),
+        }
+    }
+
+    /// Makes the executor carry out the operations of the packets it receives.
+    ///
+    /// Emulated packets complete with the status set by [`set_status`](Self::set_status), unless
+    /// the operation itself fails.
+    pub fn set_emulate(&self, emulate: bool) {
+        self.emulate.set(emulate);
+    }
+
+    /// Sets the status flags and error code written to the packets executed from now on.
+    ///
+    /// The completion bit is always set.
+    pub fn set_status(&self, bits: u8, error_code: u8) {
+        self.status.set((bits | 1, error_code));
+    }
+
+    /// Makes the executor reject the next packets with [`SlotsFull`](ExError::SlotsFull).
+    pub fn set_full(&self, full: bool) {
+        self.full.set(full);
+    }
+
+    /// Makes the executor reject the next packets with [`NotClocked`](ExError::NotClocked), while
+    /// still reporting a free slot.
+    pub fn set_clocked(&self, clocked: bool) {
+        self.clocked.set(clocked);
+    }
+
+    /// Makes the executor write `raw` to the payload of the packets terminating a hash, in
+    /// place of the digest the DCP would write.
+    ///
+    /// Meant for hashes the executor can't emulate, packets expecting a digest of another length
+    /// are left alone.
+    pub fn set_hash_output(&self, raw: &[u8]) {
+        *self.hash_output.borrow_mut() = Some(raw.to_vec());
+    }
+
+    /// The packets received so far.
+    pub fn captured(&self) -> std::cell::Ref<'_, [CapturedPacket]> {
+        std::cell::Ref::map(self.captured.borrow(), |v| v.as_slice())
+    }
+
+    /// Forgets the packets received so far.
+    pub fn clear(&self) {
+        self.captured.borrow_mut().clear();
+    }
+}
+
+impl Default for MockExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor for MockExecutor {
+    unsafe fn inner_exec(&self, task: &mut ControlPacket) -> Result<u8, ExError> {
+        if self.full.get() {
+            return Err(ExError::SlotsFull);
+        }
+        if !self.clocked.get() {
+            return Err(ExError::NotClocked);
+        }
+        let (bits, error_code) = self.status.get();
+        let mut captured = self.captured.borrow_mut();
+        let mut next = Some(task);
+        while let Some(packet) = next {
+            let capture = packet.capture();
+            captured.push(capture);
+            let bits = if self.emulate.get() {
+                self.run(packet, &capture).unwrap_or(bits)
+            } else {
+                bits
+            };
+            if let (Some(raw), Some(out)) = (&*self.hash_output.borrow(), packet.hash_output_mut())
+            {
+                if raw.len() == out.len() {
+                    out.copy_from_slice(raw);
+                }
+            }
+            packet.status.bits = bits;
+            packet.status.error_code = error_code;
+            packet.status.tag = capture.tag;
+            next = packet.next_in_chain_mut();
+        }
+        Ok(0)
+    }
+
+    fn capacity(&self) -> usize {
+        !self.full.get() as usize
+    }
+
+    fn channel(&self) -> Option<u8> {
+        Some(0)
+    }
+}
+
+impl MockExecutor {
+    /// Carries out the operation of a packet, returns the status bits if it fails.
+    ///
+    /// # Safety
+    ///
+    /// The buffers of the packet must be valid.
+    unsafe fn run(&self, packet: &ControlPacket, capture: &CapturedPacket) -> Option<u8> {
+        let has = |flag: Control0Flag| capture.control0 & flag as u32 != 0;
+        let len = packet.data_len();
+        // The hash selector is the third byte of Control1
+        let hash = Hash::try_from((capture.control1 >> 16) as u8);
+        if has(Control0Flag::EnableCipher)
+            || (has(Control0Flag::EnableHash) && hash != Ok(Hash::Crc32))
+        {
+            return Some(SETUP_ERROR);
+        }
+
+        // Source data, or the constant repeated over the length of the operation
+        let fill: Vec<u8>;
+        let input: &[u8] = if has(Control0Flag::ConstantFill) {
+            let word = (capture.source as u32).to_le_bytes();
+            fill = word.iter().copied().cycle().take(len).collect();
+            &fill
+        } else if capture.source != 0 {
+            std::slice::from_raw_parts(capture.source as *const u8, len)
+        } else {
+            &[]
+        };
+
+        if has(Control0Flag::EnableBlit) {
+            let width = capture.bufsize as u16 as usize;
+            let stride = capture.control1 as usize;
+            for (i, line) in input.chunks(width.max(1)).enumerate() {
+                let dest = (capture.dest + i * stride) as *mut u8;
+                ptr::copy(line.as_ptr(), dest, line.len());
+            }
+        } else if has(Control0Flag::EnableMemcopy) {
+            ptr::copy(input.as_ptr(), capture.dest as *mut u8, len);
+        }
+
+        if has(Control0Flag::EnableHash) {
+            if has(Control0Flag::HashInit) {
+                self.crc.set(0xFFFF_FFFF);
+            }
+            self.crc.set(crc32_mpeg2(self.crc.get(), input));
+            if has(Control0Flag::HashTerm) || has(Control0Flag::HashCheck) {
+                if capture.payload == 0 {
+                    return Some(SETUP_ERROR);
+                }
+                let digest = self.crc.get().to_le_bytes();
+                let out = (capture.payload + packet.payload_len() - 4) as *mut u8;
+                if has(Control0Flag::HashCheck) {
+                    let expected = std::slice::from_raw_parts(out, 4);
+                    if expected != digest {
+                        return Some(HASH_MISMATCH);
+                    }
+                } else {
+                    ptr::copy_nonoverlapping(digest.as_ptr(), out, 4);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// CRC-32/MPEG-2 as computed by the DCP, continuing from `crc`.
+pub(crate) fn crc32_mpeg2(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
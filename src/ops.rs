@@ -18,13 +18,54 @@ pub use crate::packet::Cipher;
 /// One-way digest calculation.
 pub use crate::packet::Hash;
 
+/// SHA-1 hash algorithm, used with [`HashSelect`].
+pub struct Sha1;
+/// SHA-256 hash algorithm, used with [`HashSelect`].
+pub struct Sha256;
+/// CRC32 checksum, used with [`HashSelect`].
+///
+/// The DCP calculates the CRC-32/MPEG-2 variant: polynomial `0x04C11DB7`, initial value
+/// `0xFFFFFFFF`, input and result not reflected, no final XOR. The digest is returned as a native
+/// `u32`.
+pub struct Crc32;
+
 /// Memcopy and hash in the same operation.
 pub type MemcopyHash = (Memcopy, Hash);
 /// Cipher and hash in the same operation.
 /// 
-/// The data can be hashed before or after the crypto operation.
+/// The data can be hashed before or after the crypto operation, see [`HashOrder`].
 pub type CipherHash = (Cipher, Hash);
 
+/// Direction of a cipher operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CipherDir {
+    Encrypt,
+    Decrypt,
+}
+
+/// Where a cipher operation reads its key from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CryptKey {
+    /// The first 16 bytes of the payload.
+    Payload,
+    /// One of the 4 key RAM slots, loaded with [`DCP::write_key`](crate::dcp::DCP::write_key).
+    KeyRam(u8),
+    /// The device unique key, derived from the OTP key.
+    Unique,
+    /// The key fused in the OTP memory.
+    Otp,
+}
+
+/// Selects which data is hashed by a [`CipherHash`] operation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashOrder {
+    /// Hash the input data, before the crypto operation (hardware default).
+    #[default]
+    BeforeCrypt,
+    /// Hash the output data, after the crypto operation.
+    AfterCrypt,
+}
+
 /// Used to configure data swapping in the FIFOs.
 pub enum SwapConfig {
     /// Assume data to be little-endian.
@@ -44,6 +85,106 @@ mod private {
     impl Sealed for super::Memcopy {}
     impl Sealed for super::Blit {}
     impl<T: Sealed, U: Sealed> Sealed for (T, U) {}
+    impl Sealed for super::Sha1 {}
+    impl Sealed for super::Sha256 {}
+    impl Sealed for super::Crc32 {}
+}
+
+/// Sealed trait selecting a hash algorithm at the type level.
+pub trait HashSelect: private::Sealed {
+    /// Algorithm selected in the packet.
+    const HASH: Hash;
+    /// Size in bytes of the digest written to the payload.
+    const PAYLOAD_BYTES: usize = Self::HASH.output_len();
+    /// Data hashed by packets that don't terminate the hash must be a multiple of this size.
+    const BLOCK_BYTES: usize = 64;
+    /// Buffer the DCP writes the digest to, [`PAYLOAD_BYTES`](Self::PAYLOAD_BYTES) long.
+    type Payload: AsMut<[u8]> + AsRef<[u8]> + Default;
+    /// Digest type.
+    type Output;
+
+    /// Converts the digest written by the DCP to the output type.
+    fn output(raw: &[u8]) -> Self::Output;
+
+    /// Converts a digest back to the layout used by the DCP, inverse of [`output`](Self::output).
+    fn raw(digest: &Self::Output) -> Self::Payload;
+}
+
+/// SHA digests are written by the DCP in reverse byte order.
+///
+/// The output swap flags of Control0 only apply to the data written to the destination buffer,
+/// not to the digest written to the payload, so the digest is put back in order in software.
+fn reversed<const N: usize>(raw: &[u8]) -> [u8; N] {
+    let mut out = [0; N];
+    out.copy_from_slice(raw);
+    out.reverse();
+    out
+}
+
+impl HashSelect for Sha1 {
+    const HASH: Hash = Hash::Sha1;
+    type Payload = [u8; 20];
+    type Output = [u8; 20];
+
+    fn output(raw: &[u8]) -> Self::Output {
+        reversed(raw)
+    }
+
+    fn raw(digest: &Self::Output) -> Self::Payload {
+        reversed(digest)
+    }
+}
+impl HashSelect for Sha256 {
+    const HASH: Hash = Hash::Sha256;
+    type Payload = [u8; 32];
+    type Output = [u8; 32];
+
+    fn output(raw: &[u8]) -> Self::Output {
+        reversed(raw)
+    }
+
+    fn raw(digest: &Self::Output) -> Self::Payload {
+        reversed(digest)
+    }
+}
+impl HashSelect for Crc32 {
+    const HASH: Hash = Hash::Crc32;
+    type Payload = [u8; 4];
+    type Output = u32;
+
+    fn output(raw: &[u8]) -> Self::Output {
+        u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]])
+    }
+
+    fn raw(digest: &Self::Output) -> Self::Payload {
+        digest.to_le_bytes()
+    }
+}
+
+/// Sealed trait giving the largest payload an operation can use, whatever its configuration.
+pub trait MaxPayload: private::Sealed {
+    /// Payload bytes used by the operation with all its options enabled.
+    const PAYLOAD_BYTES: usize;
+}
+impl MaxPayload for Memcopy {
+    const PAYLOAD_BYTES: usize = 0;
+}
+impl MaxPayload for Blit {
+    const PAYLOAD_BYTES: usize = 0;
+}
+/// Payload key and IV.
+impl MaxPayload for Cipher {
+    const PAYLOAD_BYTES: usize = 32;
+}
+/// SHA-256 digest.
+impl MaxPayload for Hash {
+    const PAYLOAD_BYTES: usize = Sha256::PAYLOAD_BYTES;
+}
+impl MaxPayload for MemcopyHash {
+    const PAYLOAD_BYTES: usize = Hash::PAYLOAD_BYTES;
+}
+impl MaxPayload for CipherHash {
+    const PAYLOAD_BYTES: usize = Cipher::PAYLOAD_BYTES + Hash::PAYLOAD_BYTES;
 }
 
 /// Sealed trait implemented for hashing operations.
@@ -56,3 +197,14 @@ impl HasHash for CipherHash {}
 pub trait HasCrypt: private::Sealed {}
 impl HasCrypt for Cipher {}
 impl HasCrypt for CipherHash {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_digest_is_little_endian() {
+        assert_eq!(Crc32::output(&[0xE7, 0xE6, 0x76, 0x03]), 0x0376_E6E7);
+        assert_eq!(Crc32::raw(&0x0376_E6E7), [0xE7, 0xE6, 0x76, 0x03]);
+    }
+}
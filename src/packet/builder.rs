@@ -2,19 +2,346 @@ use super::{BlitSize, BufSize, Cipher, Control0Flag, ControlPacket, Hash, KeySel
 use crate::ops::*;
 use core::{marker::PhantomData, mem::zeroed};
 
+/// Addresses the DCP can't access, the ITCM is mapped at the start of the address space.
+const NO_DMA: core::ops::Range<usize> = 0..0x0008_0000;
+
+/// Checks in debug builds that the DCP can reach `ptr`.
+///
+/// Buffers in the ITCM read as zeroes and writes to them are lost, without the DCP reporting an
+/// error. Only checked on the target, host addresses mean nothing to the DCP.
+fn check_dma(ptr: *const u8, what: &str) {
+    debug_assert!(
+        !cfg!(target_arch = "arm") || !NO_DMA.contains(&(ptr as usize)),
+        "{} buffer at {:p} is in ITCM, which the DCP can't access",
+        what,
+        ptr
+    );
+}
+
+/// Buffers not fit for the operation of a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BufferError {
+    /// The payload can't hold the key, IV and hash the operation uses.
+    PayloadTooSmall { needed: usize, got: usize },
+    /// The source and destination buffers have different lengths.
+    SourceDestLenMismatch { src: usize, dst: usize },
+    /// The operation reads more data than the source buffer holds.
+    SourceTooShort { src: usize, len: usize },
+    /// The operation has no data to process, which can hang the DCP.
+    Empty,
+    /// A cipher operation doesn't process a whole number of 16 byte blocks.
+    ///
+    /// The DCP doesn't pad the data, it must be padded before submission.
+    NotBlockAligned { len: usize },
+}
+
+impl core::fmt::Display for BufferError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BufferError::PayloadTooSmall { needed, got } => {
+                write!(f, "payload is {} bytes long, the operation needs {}", got, needed)
+            }
+            BufferError::SourceDestLenMismatch { src, dst } => {
+                write!(f, "source is {} bytes long, destination is {}", src, dst)
+            }
+            BufferError::SourceTooShort { src, len } => {
+                write!(f, "source is {} bytes long, the operation reads {}", src, len)
+            }
+            BufferError::Empty => f.write_str("the operation has no data to process"),
+            BufferError::NotBlockAligned { len } => {
+                write!(f, "{} bytes is not a whole number of cipher blocks", len)
+            }
+        }
+    }
+}
+
+impl core::error::Error for BufferError {}
+
+/// Returns [`SourceDestLenMismatch`](BufferError::SourceDestLenMismatch) if `src` and `dst` have
+/// different lengths.
+pub(crate) fn check_lengths(src: &[u8], dst: &[u8]) -> Result<(), BufferError> {
+    if src.len() != dst.len() {
+        return Err(BufferError::SourceDestLenMismatch {
+            src: src.len(),
+            dst: dst.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Invalid framebuffer dimensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BlitError {
+    /// The line width is zero.
+    ZeroWidth,
+    /// The buffer doesn't hold a whole number of lines.
+    NotDivisible { len: usize, width: u16 },
+    /// The buffer has more lines than the DCP can handle.
+    TooManyLines(usize),
+    /// The line stride is shorter than the width of the blit.
+    StrideTooSmall { stride: usize, width: u16 },
+    /// The line stride doesn't fit in the 32 bit stride field.
+    StrideTooLarge(usize),
+    /// The buffer can't hold the window.
+    BufferTooSmall { needed: usize, got: usize },
+    /// The framebuffer has no lines.
+    Empty,
+    /// The framebuffer doesn't have the number of lines the operation copies.
+    LineCountMismatch { expected: usize, got: u16 },
+}
+
+impl core::fmt::Display for BlitError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BlitError::ZeroWidth => f.write_str("the line width is zero"),
+            BlitError::NotDivisible { len, width } => {
+                write!(f, "{} bytes is not a whole number of {} byte lines", len, width)
+            }
+            BlitError::TooManyLines(lines) => write!(f, "{} lines is too many for a blit", lines),
+            BlitError::StrideTooSmall { stride, width } => {
+                write!(f, "stride of {} bytes is shorter than the {} byte width", stride, width)
+            }
+            BlitError::StrideTooLarge(stride) => {
+                write!(f, "stride of {} bytes doesn't fit in 32 bits", stride)
+            }
+            BlitError::BufferTooSmall { needed, got } => {
+                write!(f, "buffer is {} bytes long, the window needs {}", got, needed)
+            }
+            BlitError::Empty => f.write_str("the framebuffer has no lines"),
+            BlitError::LineCountMismatch { expected, got } => {
+                write!(f, "framebuffer has {} lines, the operation copies {}", got, expected)
+            }
+        }
+    }
+}
+
+impl core::error::Error for BlitError {}
+
+/// Destination of a blit: `height` lines of `width` bytes, each starting `stride` bytes after
+/// the previous one.
+///
+/// The DCP reads the source as a contiguous run of `width * height` bytes and writes it line by
+/// line in the framebuffer. Only the destination can have a stride, to copy a rectangle out of a
+/// larger source use one memcopy packet per line.
+///
+/// The DCP stores the width and the number of lines in 16 bit fields, so a single blit covers at
+/// most [`MAX_LINES`](Self::MAX_LINES) lines of up to 65535 bytes. Larger framebuffers have to be
+/// split in several windows, one packet each.
+///
+/// Blits can't mirror the image, see [`flip`](super::flip) for vertical flips.
+pub struct Framebuffer<'a> {
+    pub(super) buf: &'a mut [u8],
+    stride: usize,
+    width: u16,
+    height: u16,
+}
+
+impl<'a> Framebuffer<'a> {
+    /// Maximum number of lines of a single blit.
+    pub const MAX_LINES: usize = u16::MAX as usize;
+
+    /// Checks that `buf` holds a whole number of lines of `width` bytes, and no more than
+    /// [`MAX_LINES`](Self::MAX_LINES) of them.
+    pub fn new(buf: &'a mut [u8], width: u16) -> Result<Self, BlitError> {
+        if width == 0 {
+            return Err(BlitError::ZeroWidth);
+        }
+        let len = buf.len();
+        if len == 0 {
+            return Err(BlitError::Empty);
+        }
+        let height = len / width as usize;
+        if height * width as usize != len {
+            return Err(BlitError::NotDivisible { len, width });
+        }
+        if height > Self::MAX_LINES {
+            return Err(BlitError::TooManyLines(height));
+        }
+        Ok(Self {
+            buf,
+            stride: width as usize,
+            width,
+            height: height as u16,
+        })
+    }
+
+    /// A `width` by `height` window in a framebuffer with lines `stride` bytes long.
+    ///
+    /// `buf` starts at the top left byte of the window, all sizes are in bytes.
+    pub fn window(
+        buf: &'a mut [u8],
+        stride: usize,
+        width: u16,
+        height: u16,
+    ) -> Result<Self, BlitError> {
+        if width == 0 {
+            return Err(BlitError::ZeroWidth);
+        }
+        if height == 0 {
+            return Err(BlitError::Empty);
+        }
+        if stride < width as usize {
+            return Err(BlitError::StrideTooSmall { stride, width });
+        }
+        if stride > u32::MAX as usize {
+            return Err(BlitError::StrideTooLarge(stride));
+        }
+        let needed = (height as usize - 1)
+            .checked_mul(stride)
+            .and_then(|n| n.checked_add(width as usize))
+            .unwrap_or(usize::MAX);
+        if buf.len() < needed {
+            return Err(BlitError::BufferTooSmall {
+                needed,
+                got: buf.len(),
+            });
+        }
+        Ok(Self {
+            buf,
+            stride,
+            width,
+            height,
+        })
+    }
+
+    /// Line width in bytes.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Number of lines.
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Distance in bytes between the start of two lines.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+}
+
+/// Typestate of a [`PacketBuilder`] without a source.
+pub struct NoSource;
+/// Typestate of a [`PacketBuilder`] with a source buffer or constant.
+pub struct HasSource;
+/// Typestate of a [`PacketBuilder`] without a destination.
+pub struct NoDest;
+/// Typestate of a [`PacketBuilder`] with a destination buffer.
+pub struct HasDest;
+
 /// Constructs a control packet for the given operation.
 ///
 /// The options will be different based on the operation.
-pub struct PacketBuilder<'a, T> {
+/// `S` and `D` track whether the source and destination are set: the builder only converts to a
+/// [`ControlPacket`] once the buffers needed by the operation are, see [`Complete`].
+pub struct PacketBuilder<'a, T, S = NoSource, D = NoDest> {
     raw: ControlPacket<'a>,
-    _marker: PhantomData<T>,
+    /// Length of the payload buffer.
+    payload_len: usize,
+    /// Length of the source buffer, if known.
+    source_len: Option<usize>,
+    /// Length of the destination buffer, if set from a slice.
+    dest_len: Option<usize>,
+    _marker: PhantomData<(T, S, D)>,
 }
 
-impl<'a, T> PacketBuilder<'a, T> {
+/// Implemented by the builders that set all the buffers their operation needs.
+///
+/// Hashing needs a source, the other operations a source and a destination. The payload
+/// is not tracked, use [`PacketBuilder::build`] to check it.
+pub trait Complete {}
+
+impl<'a> Complete for PacketBuilder<'a, Memcopy, HasSource, HasDest> {}
+impl<'a> Complete for PacketBuilder<'a, Blit, HasSource, HasDest> {}
+impl<'a> Complete for PacketBuilder<'a, Cipher, HasSource, HasDest> {}
+impl<'a, D> Complete for PacketBuilder<'a, Hash, HasSource, D> {}
+impl<'a> Complete for PacketBuilder<'a, MemcopyHash, HasSource, HasDest> {}
+impl<'a> Complete for PacketBuilder<'a, CipherHash, HasSource, HasDest> {}
+
+impl<'a, T, S, D> PacketBuilder<'a, T, S, D> {
+    /// Moves the builder to another typestate.
+    fn state<S2, D2>(self) -> PacketBuilder<'a, T, S2, D2> {
+        PacketBuilder {
+            raw: self.raw,
+            payload_len: self.payload_len,
+            source_len: self.source_len,
+            dest_len: self.dest_len,
+            _marker: PhantomData,
+        }
+    }
+
     /// Set the source buffer or constant for the operation
-    pub fn source(mut self, source: Source<'a>) -> Self {
+    ///
+    /// The length of the source is not known, [`build`](Self::build) can't check it.
+    pub fn source(mut self, source: Source<'a>) -> PacketBuilder<'a, T, HasSource, D> {
         self.raw.source = source;
-        self
+        self.source_len = None;
+        self.state()
+    }
+
+    /// Set the source buffer and use its length as the buffer size.
+    ///
+    /// Used by operations without a destination buffer, like hashing. When a destination is
+    /// already set the operation keeps its length, and [`build`](Self::build) checks that the
+    /// source is long enough.
+    pub fn input(self, slice: &'a [u8]) -> PacketBuilder<'a, T, HasSource, D> {
+        // The slice is borrowed for 'a
+        unsafe { self.input_raw(slice.as_ptr(), slice.len()) }
+    }
+
+    /// Same as [`input`](Self::input), with the buffer as a raw pointer.
+    ///
+    /// For buffers also written by another packet, where a shared slice would alias the mutable
+    /// one of the other packet.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads of `len` bytes for `'a`.
+    pub(crate) unsafe fn input_raw(
+        mut self,
+        ptr: *const u8,
+        len: usize,
+    ) -> PacketBuilder<'a, T, HasSource, D> {
+        check_dma(ptr, "source");
+        self.raw.source = Source { pointer: ptr };
+        // The destination can't hold more than its own length
+        if self.raw.dest.is_null() {
+            self.raw.bufsize = BufSize::of_len(len);
+        }
+        self.source_len = Some(len);
+        self.state()
+    }
+
+    /// Set the source and destination buffers for the operation.
+    ///
+    /// Returns [`SourceDestLenMismatch`](BufferError::SourceDestLenMismatch) if they have
+    /// different lengths, [`Empty`](BufferError::Empty) if they are empty.
+    pub fn buffers(
+        self,
+        src: &'a [u8],
+        dst: &'a mut [u8],
+    ) -> Result<PacketBuilder<'a, T, HasSource, HasDest>, BufferError> {
+        check_lengths(src, dst)?;
+        if dst.is_empty() {
+            return Err(BufferError::Empty);
+        }
+        Ok(self.buffers_unchecked(src, dst))
+    }
+
+    /// Same as [`buffers`](Self::buffers), without checking the lengths.
+    ///
+    /// The operation runs on `dst.len()` bytes, `src` must be at least as long.
+    pub fn buffers_unchecked(
+        self,
+        src: &'a [u8],
+        dst: &'a mut [u8],
+    ) -> PacketBuilder<'a, T, HasSource, HasDest> {
+        check_dma(src.as_ptr(), "source");
+        let mut builder = self.source(Source::from_slice(src));
+        builder.source_len = Some(src.len());
+        builder.dest(dst)
     }
 
     /// Set the destination buffer for the operation
@@ -23,13 +350,50 @@ impl<'a, T> PacketBuilder<'a, T> {
     ///
     /// The destination buffer lenght must be lower than or
     /// equal to the source buffer size to prevent out of
-    /// bounds access.
-    pub fn dest(mut self, slice: &'a mut [u8]) -> Self {
-        self.raw.dest = slice as *mut [u8] as *mut u8;
-        self.raw.bufsize = BufSize {
-            buf: slice.len() as u32,
-        };
-        self
+    /// bounds access. [`build`](Self::build) checks it when
+    /// the source was set from a slice, use
+    /// [`dest_checked`](Self::dest_checked) to check it here.
+    pub fn dest(self, slice: &'a mut [u8]) -> PacketBuilder<'a, T, S, HasDest> {
+        // The slice is borrowed for 'a
+        unsafe { self.dest_raw(slice.as_mut_ptr(), slice.len()) }
+    }
+
+    /// Same as [`dest`](Self::dest), with the buffer as a raw pointer.
+    ///
+    /// For buffers also read by another packet, where a mutable slice would alias the shared one
+    /// of the other packet.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes of `len` bytes for `'a`.
+    pub(crate) unsafe fn dest_raw(
+        mut self,
+        ptr: *mut u8,
+        len: usize,
+    ) -> PacketBuilder<'a, T, S, HasDest> {
+        check_dma(ptr, "destination");
+        self.raw.bufsize = BufSize::of_len(len);
+        self.raw.dest = ptr;
+        self.dest_len = Some(len);
+        self.state()
+    }
+
+    /// Same as [`dest`](Self::dest), but checks the length against the source.
+    ///
+    /// Returns [`SourceTooShort`](BufferError::SourceTooShort) if the source was set from a
+    /// slice shorter than `slice`. When the source is set later, [`build`](Self::build) (or
+    /// `into` in debug builds) does the check.
+    pub fn dest_checked(
+        self,
+        slice: &'a mut [u8],
+    ) -> Result<PacketBuilder<'a, T, S, HasDest>, BufferError> {
+        match self.source_len {
+            Some(src) if src < slice.len() => Err(BufferError::SourceTooShort {
+                src,
+                len: slice.len(),
+            }),
+            _ => Ok(self.dest(slice)),
+        }
     }
 
     /// Set the payload buffer for the operation
@@ -37,12 +401,16 @@ impl<'a, T> PacketBuilder<'a, T> {
     /// # Safety
     ///
     /// The payload must hold whatever the operation requires,
-    /// for a maximum of 64 bytes.
-    /// AES CBC cipher init takes a 16 byte IV,
-    /// HashTerm needs 20B for SHA1 or 32 for SHA2 and the expected
+    /// for a maximum of 64 bytes, in this order:
+    /// the 16 byte key when using a payload key,
+    /// the 16 byte IV for AES CBC cipher init,
+    /// then the hash output for HashTerm ([`Hash::output_len`] bytes:
+    /// 20 for SHA1, 32 for SHA256, 4 for CRC32). The expected
     /// hash is read from there if the HashCheck flag is set.
     pub fn payload(mut self, slice: &'a mut [u8]) -> Self {
+        check_dma(slice.as_ptr(), "payload");
         self.raw.payload = slice as *mut [u8] as *mut u8;
+        self.payload_len = slice.len();
         self
     }
 
@@ -87,15 +455,96 @@ impl<'a, T> PacketBuilder<'a, T> {
         self
     }
 
+    /// Chain the packet to `next`, which the DCP runs once this one is complete.
+    ///
+    /// Chains can be any length, only the head has to be passed to the executor. Only the last
+    /// packet of the chain must [`decr_semaphore`](Self::decr_semaphore).
+    pub fn chain_to(mut self, next: &'a mut ControlPacket<'a>) -> Self {
+        self.raw.next = next;
+        self.raw.control0 = self.raw.control0.flag(Control0Flag::Chain);
+        self
+    }
+
+    /// Chain the packet to the one right after it in memory.
+    ///
+    /// Unlike [`chain_to`](Self::chain_to) the DCP ignores the next packet pointer and loads
+    /// the following packet of the array, this is what
+    /// [`exec_slice`](crate::ex::Executor::exec_slice) sets on all the packets but the last one.
+    ///
+    /// # Safety
+    ///
+    /// The packet must be followed in memory by another packet, e.g. by building it in an array
+    /// of packets that stays alive and in place until the chain completes.
+    pub unsafe fn chain_continuous(mut self) -> Self {
+        self.raw.control0 = self.raw.control0.flag(Control0Flag::ChainContinuous);
+        self
+    }
+
     /// Fire a DCP_IRQ interrupt on operation completion.
     pub fn interrupt_enable(mut self) -> Self {
         self.raw.control0 = self.raw.control0.flag(Control0Flag::InterruptEnable);
         self
     }
+
+    /// Builds the packet, checking that the payload is large enough for the operation, that
+    /// operations writing to a destination have some data to process, that they don't read past
+    /// the end of the source and that cipher operations process whole blocks.
+    ///
+    /// Converting the builder with [`From`] skips the checks.
+    pub fn build(self) -> Result<ControlPacket<'a>, BufferError>
+    where
+        Self: Complete,
+    {
+        // Hashing no data is fine, moving no data is not
+        if !self.raw.dest.is_null() && self.raw.data_len() == 0 {
+            return Err(BufferError::Empty);
+        }
+        let len = self.raw.data_len();
+        if self.raw.control0.has(Control0Flag::EnableCipher) && len & 0xF != 0 {
+            return Err(BufferError::NotBlockAligned { len });
+        }
+        match self.source_len {
+            Some(src) if src < len => return Err(BufferError::SourceTooShort { src, len }),
+            _ => (),
+        }
+        match self.dest_len {
+            Some(dst) if dst < len => {
+                return Err(BufferError::SourceDestLenMismatch { src: len, dst })
+            }
+            _ => (),
+        }
+        let needed = self.raw.payload_len();
+        if needed > self.payload_len {
+            return Err(BufferError::PayloadTooSmall {
+                needed,
+                got: self.payload_len,
+            });
+        }
+        Ok(self.raw)
+    }
 }
 
-impl<'a, T> From<PacketBuilder<'a, T>> for ControlPacket<'a> {
-    fn from(builder: PacketBuilder<'a, T>) -> Self {
+impl<'a, T, S, D> From<PacketBuilder<'a, T, S, D>> for ControlPacket<'a>
+where
+    PacketBuilder<'a, T, S, D>: Complete,
+{
+    /// Skips the checks of [`build`](PacketBuilder::build).
+    ///
+    /// Debug builds still panic if the source or the destination was set from a slice shorter
+    /// than the data.
+    fn from(builder: PacketBuilder<'a, T, S, D>) -> Self {
+        let len = builder.raw.data_len();
+        if let Some(src) = builder.source_len {
+            debug_assert!(src >= len, "source is {} bytes long, the operation reads {}", src, len);
+        }
+        if let Some(dst) = builder.dest_len {
+            debug_assert!(
+                dst >= len,
+                "destination is {} bytes long, the operation writes {}",
+                dst,
+                len
+            );
+        }
         builder.raw
     }
 }
@@ -109,6 +558,9 @@ impl<'a> PacketBuilder<'a, Cipher> {
             .flag(Control0Flag::PayloadKey);
         Self {
             raw,
+            payload_len: 0,
+            source_len: None,
+            dest_len: None,
             _marker: PhantomData,
         }
     }
@@ -126,6 +578,9 @@ impl<'a> PacketBuilder<'a, Hash> {
         raw.control0 = raw.control0.flag(Control0Flag::EnableHash);
         Self {
             raw,
+            payload_len: 0,
+            source_len: None,
+            dest_len: None,
             _marker: PhantomData,
         }
     }
@@ -143,11 +598,28 @@ impl<'a> PacketBuilder<'a, Memcopy> {
         raw.control0 = raw.control0.flag(Control0Flag::EnableMemcopy);
         Self {
             raw,
+            payload_len: 0,
+            source_len: None,
+            dest_len: None,
             _marker: PhantomData,
         }
     }
 }
 
+impl<'a, S, D> PacketBuilder<'a, Memcopy, S, D> {
+    /// Fill the destination with a constant instead of copying a source buffer.
+    ///
+    /// The DCP replicates the 32 bit word across the destination, storing it little endian:
+    /// `0x44332211` fills the buffer with `11 22 33 44 11 22 ..`. Use
+    /// `u32::from_ne_bytes([b; 4])` to fill it with a single byte.
+    pub fn constant(mut self, word: u32) -> PacketBuilder<'a, Memcopy, HasSource, D> {
+        self.raw.source = Source { constant: word };
+        self.source_len = None;
+        self.raw.control0 = self.raw.control0.flag(Control0Flag::ConstantFill);
+        self.state()
+    }
+}
+
 impl<'a> Default for PacketBuilder<'a, Memcopy> {
     fn default() -> Self {
         Self::new()
@@ -160,23 +632,43 @@ impl<'a> PacketBuilder<'a, Blit> {
         raw.control0 = raw.control0.flag(Control0Flag::EnableBlit);
         Self {
             raw,
+            payload_len: 0,
+            source_len: None,
+            dest_len: None,
             _marker: PhantomData,
         }
     }
+}
 
+impl<'a, S, D> PacketBuilder<'a, Blit, S, D> {
     /// Set the destination framebuffer.
     ///
-    /// Takes an output buffer and a line width in bytes as input.
-    pub fn framebuffer(mut self, buf: &'a mut [u8], width: u16) -> Self {
-        self.raw.dest = buf as *mut [u8] as *mut u8;
+    /// The source must hold `width * height` contiguous bytes.
+    pub fn framebuffer(mut self, fb: Framebuffer<'a>) -> PacketBuilder<'a, Blit, S, HasDest> {
+        check_dma(fb.buf.as_ptr(), "framebuffer");
+        self.raw.dest = fb.buf as *mut [u8] as *mut u8;
         self.raw.bufsize = BufSize {
             blit: BlitSize {
-                width,
-                height: (buf.len() / width as usize) as u16,
+                width: fb.width,
+                height: fb.height,
             },
         };
-        self.raw.control1.blit_size = buf.len() as u16;
-        self
+        // Framebuffer checked that the window fits
+        self.dest_len = None;
+        self.raw.control1.blit_stride = fb.stride as u32;
+        self.state()
+    }
+
+    /// Fill the framebuffer with a constant, to draw a solid rectangle.
+    ///
+    /// The word is replicated like in [`Memcopy`] constant fills, and the pattern carries on from
+    /// one line to the next: keep the width a multiple of 4 bytes, or use a word made of the
+    /// same byte, for every line to start with the same byte.
+    pub fn constant(mut self, word: u32) -> PacketBuilder<'a, Blit, HasSource, D> {
+        self.raw.source = Source { constant: word };
+        self.source_len = None;
+        self.raw.control0 = self.raw.control0.flag(Control0Flag::ConstantFill);
+        self.state()
     }
 }
 
@@ -195,6 +687,9 @@ impl<'a> PacketBuilder<'a, MemcopyHash> {
             .flag(Control0Flag::EnableMemcopy);
         Self {
             raw,
+            payload_len: 0,
+            source_len: None,
+            dest_len: None,
             _marker: PhantomData,
         }
     }
@@ -212,21 +707,37 @@ impl<'a> PacketBuilder<'a, CipherHash> {
         raw.control0 = raw
             .control0
             .flag(Control0Flag::EnableCipher)
-            .flag(Control0Flag::EnableMemcopy);
+            .flag(Control0Flag::PayloadKey)
+            .flag(Control0Flag::EnableHash);
         Self {
             raw,
+            payload_len: 0,
+            source_len: None,
+            dest_len: None,
             _marker: PhantomData,
         }
     }
 }
 
+impl<'a, S, D> PacketBuilder<'a, CipherHash, S, D> {
+    /// Choose whether the plaintext or the ciphertext is hashed when encrypting (or the
+    /// other way around when decrypting).
+    pub fn hash_order(mut self, order: HashOrder) -> Self {
+        self.raw.control0 = match order {
+            HashOrder::BeforeCrypt => self.raw.control0.unflag(Control0Flag::HashOutput),
+            HashOrder::AfterCrypt => self.raw.control0.flag(Control0Flag::HashOutput),
+        };
+        self
+    }
+}
+
 impl<'a> Default for PacketBuilder<'a, CipherHash> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<'a, T: HasHash> PacketBuilder<'a, T> {
+impl<'a, T: HasHash, S, D> PacketBuilder<'a, T, S, D> {
     /// Set the hashing algorhitm.
     pub fn hash(mut self, hash: Hash) -> Self {
         self.raw.control1.crypto.hash = hash;
@@ -251,25 +762,58 @@ impl<'a, T: HasHash> PacketBuilder<'a, T> {
         self.raw.control0 = self.raw.control0.flag(Control0Flag::HashCheck);
         self
     }
+    /// Select the hashing algorithm `H` and set a payload big enough for its digest.
+    ///
+    /// Payloads shorter than [`H::PAYLOAD_BYTES`](HashSelect::PAYLOAD_BYTES) fail to compile.
+    /// [`build`](Self::build) still checks that the payload has room for the key and IV.
+    pub fn hash_payload<H: HashSelect, const N: usize>(self, payload: &'a mut [u8; N]) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = PayloadFits::<H, N>::OK;
+        self.hash(H::HASH).payload(payload)
+    }
+}
+
+impl<'a, T: MaxPayload, S, D> PacketBuilder<'a, T, S, D> {
+    /// Set a payload big enough for any configuration of the operation.
+    ///
+    /// Payloads shorter than [`T::PAYLOAD_BYTES`](MaxPayload::PAYLOAD_BYTES) fail to compile, so
+    /// [`build`](Self::build) never rejects the payload.
+    pub fn payload_fixed<const N: usize>(self, payload: &'a mut [u8; N]) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = FixedPayloadFits::<T, N>::OK;
+        self.payload(payload)
+    }
+}
+
+/// Compile time check that a payload of `N` bytes fits any configuration of `T`.
+struct FixedPayloadFits<T, const N: usize>(PhantomData<T>);
+
+impl<T: MaxPayload, const N: usize> FixedPayloadFits<T, N> {
+    const OK: () = assert!(N >= T::PAYLOAD_BYTES, "payload too small for the operation");
+}
+
+/// Compile time check that a payload of `N` bytes holds the digest of `H`.
+struct PayloadFits<H, const N: usize>(PhantomData<H>);
+
+impl<H: HashSelect, const N: usize> PayloadFits<H, N> {
+    const OK: () = assert!(N >= H::PAYLOAD_BYTES, "payload too small for the digest");
 }
 
-impl<'a, T: HasCrypt> PacketBuilder<'a, T> {
+impl<'a, T: HasCrypt, S, D> PacketBuilder<'a, T, S, D> {
     /// Perform encryption in-place, without separate source and destination buffers
-    pub fn in_place(self, buf: &mut [u8]) -> Self {
+    pub fn in_place(mut self, buf: &'a mut [u8]) -> PacketBuilder<'a, T, HasSource, HasDest> {
+        check_dma(buf.as_ptr(), "in-place");
         let ptr = buf as *mut [u8] as *mut u8;
-        Self {
-            raw: ControlPacket {
-                source: Source {
-                    pointer: ptr as *const u8,
-                },
-                dest: ptr,
-                bufsize: BufSize {
-                    buf: buf.len() as u32,
-                },
-                ..self.raw
-            },
-            ..self
-        }
+        self.raw.source = Source {
+            pointer: ptr as *const u8,
+        };
+        self.raw.dest = ptr;
+        self.raw.bufsize = BufSize {
+            buf: buf.len() as u32,
+        };
+        self.source_len = Some(buf.len());
+        self.dest_len = Some(buf.len());
+        self.state()
     }
 
     /// Select the encryption algorhitm.
@@ -279,8 +823,64 @@ impl<'a, T: HasCrypt> PacketBuilder<'a, T> {
     }
 
     /// Select the source for the encryption key.
+    ///
+    /// The key is no longer read from the payload.
     pub fn key(mut self, key: KeySelect) -> Self {
         self.raw.control1.crypto.key = key;
+        self.raw.control0 = self.raw.control0.unflag(Control0Flag::PayloadKey);
+        self
+    }
+
+    /// Select where the key is read from.
+    ///
+    /// Sets the key selector together with the payload and OTP key flags.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a key RAM slot above 3 is selected.
+    pub fn with_key(mut self, key: CryptKey) -> Self {
+        let (select, flag) = match key {
+            CryptKey::Payload => (KeySelect::Key0, Some(Control0Flag::PayloadKey)),
+            CryptKey::KeyRam(0) => (KeySelect::Key0, None),
+            CryptKey::KeyRam(1) => (KeySelect::Key1, None),
+            CryptKey::KeyRam(2) => (KeySelect::Key2, None),
+            CryptKey::KeyRam(3) => (KeySelect::Key3, None),
+            CryptKey::KeyRam(slot) => panic!("invalid key RAM slot {}", slot),
+            CryptKey::Unique => (KeySelect::UniqueKey, Some(Control0Flag::OtpKey)),
+            CryptKey::Otp => (KeySelect::OtpKey, Some(Control0Flag::OtpKey)),
+        };
+        self.raw.control1.crypto.key = select;
+        self.raw.control0 = self
+            .raw
+            .control0
+            .unflag(Control0Flag::PayloadKey)
+            .unflag(Control0Flag::OtpKey);
+        if let Some(flag) = flag {
+            self.raw.control0 = self.raw.control0.flag(flag);
+        }
+        self
+    }
+
+    /// Use the key fused in the OTP memory.
+    ///
+    /// Typically used to decrypt a secure boot image.
+    pub fn otp_key(self) -> Self {
+        self.with_key(CryptKey::Otp)
+    }
+
+    /// Use the device unique key, binding the data to this chip.
+    pub fn unique_key(self) -> Self {
+        self.with_key(CryptKey::Unique)
+    }
+
+    /// Set the cipher configuration byte of Control1.
+    ///
+    /// The reference manual describes it as optional configuration bits for the cipher, but
+    /// doesn't define any for the AES modes of the i.MX RT, where it should be left at zero. Only
+    /// set it for cipher options not covered by [`cipher`](Self::cipher): the DCP doesn't reject
+    /// unknown values, and the output will be garbage if they select the wrong mode.
+    pub fn cipher_config(mut self, config: u8) -> Self {
+        self.raw.control1.crypto.cipher_config = config;
         self
     }
 
@@ -290,23 +890,485 @@ impl<'a, T: HasCrypt> PacketBuilder<'a, T> {
         self
     }
 
+    /// Initialize AES CBC with `iv`.
+    ///
+    /// Copies the IV to the payload, right after the key if it is read from the payload:
+    ///
+    /// | Payload key | Offset 0 | Offset 16 |
+    /// |-------------|----------|-----------|
+    /// | yes         | key      | IV        |
+    /// | no          | IV       |           |
+    ///
+    /// Call it after [`payload`](Self::payload) and [`key`](Self::key).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the payload is too short to hold the IV.
+    pub fn iv(self, iv: &[u8; 16]) -> Self {
+        let offset = if self.raw.control0.has(Control0Flag::PayloadKey) {
+            16
+        } else {
+            0
+        };
+        assert!(
+            self.payload_len >= offset + 16,
+            "the payload is too short to hold the IV"
+        );
+        unsafe { core::ptr::copy_nonoverlapping(iv.as_ptr(), self.raw.payload.add(offset), 16) };
+        self.cipher_init()
+    }
+
     /// Encrypt the data (defaults to decryption).
     pub fn encrypt(mut self) -> Self {
         self.raw.control0 = self.raw.control0.flag(Control0Flag::CipherEncrypt);
         self
     }
 
+    /// Decrypt the data, which is the default.
+    pub fn decrypt(mut self) -> Self {
+        self.raw.control0 = self.raw.control0.unflag(Control0Flag::CipherEncrypt);
+        self
+    }
+
+    /// Set the direction of the cipher operation.
+    pub fn direction(self, dir: CipherDir) -> Self {
+        match dir {
+            CipherDir::Encrypt => self.encrypt(),
+            CipherDir::Decrypt => self.decrypt(),
+        }
+    }
+
     /// Configure data swapping in the key in the payload section.
     pub fn key_swap(mut self, conf: SwapConfig) -> Self {
         let ctl0 = self.raw.control0;
         self.raw.control0 = match conf {
             SwapConfig::Keep => ctl0,
-            SwapConfig::WordSwap => ctl0.flag(Control0Flag::OutputWordSwap),
-            SwapConfig::ByteSwap => ctl0.flag(Control0Flag::OutputByteSwap),
+            SwapConfig::WordSwap => ctl0.flag(Control0Flag::KeyWordSwap),
+            SwapConfig::ByteSwap => ctl0.flag(Control0Flag::KeyByteSwap),
             SwapConfig::WordByteSwap => ctl0
-                .flag(Control0Flag::OutputWordSwap)
-                .flag(Control0Flag::OutputByteSwap),
+                .flag(Control0Flag::KeyWordSwap)
+                .flag(Control0Flag::KeyByteSwap),
         };
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ex::Executor,
+        mock::{CapturedPacket, MockExecutor},
+    };
+
+    /// Runs a packet on the mock and returns what the executor received.
+    macro_rules! run {
+        ($packet:expr) => {{
+            let mock = MockExecutor::new();
+            let mut packet: ControlPacket = $packet;
+            mock.exec_one(&mut packet).unwrap().wait().unwrap();
+            let captured: CapturedPacket = mock.captured()[0];
+            captured
+        }};
+    }
+
+    #[test]
+    fn sha256_payload_holds_the_digest() {
+        let data = [0u8; 64];
+        let mut short = [0u8; 20];
+        let res = PacketBuilder::<Hash>::new()
+            .hash(Hash::Sha256)
+            .hash_init()
+            .hash_term()
+            .input(&data)
+            .payload(&mut short)
+            .build();
+        assert_eq!(res.err(), Some(BufferError::PayloadTooSmall { needed: 32, got: 20 }));
+
+        let mut payload = [0u8; 32];
+        let packet = PacketBuilder::<Hash>::new()
+            .hash(Hash::Sha256)
+            .hash_init()
+            .hash_term()
+            .input(&data)
+            .payload(&mut payload)
+            .decr_semaphore()
+            .build()
+            .unwrap();
+        assert_eq!(run!(packet).control1 >> 16 & 0xFF, Hash::Sha256 as u32);
+    }
+
+    #[test]
+    fn cipher_hash_payload_sums_key_and_digest() {
+        let src = [0u8; 32];
+        let mut dst = [0u8; 32];
+        let mut payload = [0u8; 32];
+        let res = PacketBuilder::<CipherHash>::new()
+            .cipher(Cipher::Aes128Ecb)
+            .hash(Hash::Sha256)
+            .hash_init()
+            .hash_term()
+            .buffers(&src, &mut dst)
+            .unwrap()
+            .payload(&mut payload)
+            .build();
+        assert_eq!(res.err(), Some(BufferError::PayloadTooSmall { needed: 48, got: 32 }));
+    }
+
+    #[test]
+    fn key_swap_sets_the_key_flags() {
+        let mut buf = [0u8; 16];
+        let mut key = [0u8; 16];
+        let captured = run!(PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Ecb)
+            .key_swap(SwapConfig::ByteSwap)
+            .in_place(&mut buf)
+            .payload(&mut key)
+            .decr_semaphore()
+            .build()
+            .unwrap());
+        assert_ne!(captured.control0 & 1 << 18, 0);
+        assert_eq!(captured.control0 & (1 << 22 | 1 << 23), 0);
+    }
+
+    #[test]
+    fn iv_follows_the_payload_key() {
+        let iv = [0xA5u8; 16];
+        let mut buf = [0u8; 16];
+        let mut payload = [0u8; 32];
+        let captured = run!(PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Cbc)
+            .with_key(CryptKey::Payload)
+            .in_place(&mut buf)
+            .payload(&mut payload)
+            .iv(&iv)
+            .decr_semaphore()
+            .build()
+            .unwrap());
+        assert_ne!(captured.control0 & 1 << 9, 0);
+        assert_eq!(payload[..16], [0; 16]);
+        assert_eq!(payload[16..], iv);
+
+        let mut payload = [0u8; 16];
+        let captured = run!(PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Cbc)
+            .with_key(CryptKey::KeyRam(1))
+            .in_place(&mut buf)
+            .payload(&mut payload)
+            .iv(&iv)
+            .decr_semaphore()
+            .build()
+            .unwrap());
+        assert_ne!(captured.control0 & 1 << 9, 0);
+        assert_eq!(payload, iv);
+    }
+
+    #[test]
+    fn hash_order_sets_the_hash_output_flag() {
+        let mut buf = [0u8; 16];
+        let mut payload = [0u8; 48];
+        let before = run!(PacketBuilder::<CipherHash>::new()
+            .cipher(Cipher::Aes128Ecb)
+            .hash(Hash::Sha256)
+            .hash_order(HashOrder::BeforeCrypt)
+            .in_place(&mut buf)
+            .payload(&mut payload)
+            .decr_semaphore()
+            .build()
+            .unwrap());
+        let after = run!(PacketBuilder::<CipherHash>::new()
+            .cipher(Cipher::Aes128Ecb)
+            .hash(Hash::Sha256)
+            .hash_order(HashOrder::AfterCrypt)
+            .in_place(&mut buf)
+            .payload(&mut payload)
+            .decr_semaphore()
+            .build()
+            .unwrap());
+        assert_eq!(before.control0 & 1 << 15, 0);
+        assert_ne!(after.control0 & 1 << 15, 0);
+    }
+
+    #[test]
+    fn with_key_sets_selector_and_flags() {
+        let cases = [
+            (CryptKey::Payload, KeySelect::Key0, 1 << 11),
+            (CryptKey::KeyRam(0), KeySelect::Key0, 0),
+            (CryptKey::KeyRam(3), KeySelect::Key3, 0),
+            (CryptKey::Unique, KeySelect::UniqueKey, 1 << 10),
+            (CryptKey::Otp, KeySelect::OtpKey, 1 << 10),
+        ];
+        for (key, select, flags) in cases {
+            let mut buf = [0u8; 16];
+            let mut payload = [0u8; 16];
+            let captured = run!(PacketBuilder::<Cipher>::new()
+                .cipher(Cipher::Aes128Ecb)
+                .with_key(CryptKey::Otp)
+                .with_key(key)
+                .in_place(&mut buf)
+                .payload(&mut payload)
+                .decr_semaphore()
+                .build()
+                .unwrap());
+            assert_eq!(captured.control1 >> 8 & 0xFF, select as u32, "{:?}", key);
+            assert_eq!(captured.control0 & (1 << 10 | 1 << 11), flags, "{:?}", key);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid key RAM slot")]
+    fn with_key_rejects_missing_slots() {
+        let _ = PacketBuilder::<Cipher>::new().with_key(CryptKey::KeyRam(4));
+    }
+
+    #[test]
+    fn otp_key_replaces_the_payload_key() {
+        let mut buf = [0u8; 16];
+        let mut payload = [0u8; 16];
+        let captured = run!(PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Ecb)
+            .with_key(CryptKey::Payload)
+            .otp_key()
+            .in_place(&mut buf)
+            .payload(&mut payload)
+            .decr_semaphore()
+            .build()
+            .unwrap());
+        assert_eq!(captured.control1 >> 8 & 0xFF, 0xFF);
+        assert_ne!(captured.control0 & 1 << 10, 0);
+        assert_eq!(captured.control0 & 1 << 11, 0);
+    }
+
+    #[test]
+    fn unique_key_replaces_the_payload_key() {
+        let mut buf = [0u8; 16];
+        let mut payload = [0u8; 16];
+        let captured = run!(PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Ecb)
+            .with_key(CryptKey::Payload)
+            .unique_key()
+            .in_place(&mut buf)
+            .payload(&mut payload)
+            .decr_semaphore()
+            .build()
+            .unwrap());
+        assert_eq!(captured.control1 >> 8 & 0xFF, 0xFE);
+        assert_eq!(captured.control0 & 1 << 11, 0);
+    }
+
+    #[test]
+    fn empty_buffers_are_rejected() {
+        let src: [u8; 0] = [];
+        let mut dst: [u8; 0] = [];
+        let res = PacketBuilder::<Memcopy>::new().buffers(&src, &mut dst);
+        assert_eq!(res.err(), Some(BufferError::Empty));
+
+        let res = PacketBuilder::<Memcopy>::new()
+            .constant(0)
+            .dest(&mut dst)
+            .decr_semaphore()
+            .build();
+        assert_eq!(res.err(), Some(BufferError::Empty));
+
+        let mut key = [0u8; 16];
+        let res = PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Ecb)
+            .in_place(&mut dst)
+            .payload(&mut key)
+            .decr_semaphore()
+            .build();
+        assert_eq!(res.err(), Some(BufferError::Empty));
+
+        let mut payload = [0u8; 36];
+        let res = PacketBuilder::<MemcopyHash>::new()
+            .hash(Hash::Crc32)
+            .buffers_unchecked(&src, &mut dst)
+            .payload(&mut payload)
+            .decr_semaphore()
+            .build();
+        assert_eq!(res.err(), Some(BufferError::Empty));
+
+        assert_eq!(Framebuffer::new(&mut dst, 4).err(), Some(BlitError::Empty));
+        let mut fb = [0u8; 16];
+        assert_eq!(Framebuffer::window(&mut fb, 4, 4, 0).err(), Some(BlitError::Empty));
+    }
+
+    #[test]
+    fn hashing_no_data_is_allowed() {
+        let mut payload = [0u8; 4];
+        let res = PacketBuilder::<Hash>::new()
+            .hash(Hash::Crc32)
+            .hash_init()
+            .hash_term()
+            .input(&[])
+            .payload(&mut payload)
+            .decr_semaphore()
+            .build();
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn cipher_data_must_be_whole_blocks() {
+        let mut buf = [0u8; 17];
+        let mut key = [0u8; 16];
+        let res = PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Cbc)
+            .in_place(&mut buf)
+            .payload(&mut key)
+            .decr_semaphore()
+            .build();
+        assert_eq!(res.err(), Some(BufferError::NotBlockAligned { len: 17 }));
+
+        let captured = run!(PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Cbc)
+            .in_place(&mut buf[..16])
+            .payload(&mut key)
+            .decr_semaphore()
+            .build()
+            .unwrap());
+        assert_eq!(captured.bufsize, 16);
+    }
+
+    #[test]
+    fn chain_to_links_the_next_packet() {
+        let mock = MockExecutor::new();
+        let (src1, mut dst1) = ([1u8; 16], [0u8; 16]);
+        let (src2, mut dst2) = ([2u8; 16], [0u8; 16]);
+        let mut second: ControlPacket = PacketBuilder::<Memcopy>::new()
+            .buffers(&src2, &mut dst2)
+            .unwrap()
+            .decr_semaphore()
+            .build()
+            .unwrap();
+        let next = &second as *const ControlPacket as usize;
+        let mut first: ControlPacket = PacketBuilder::<Memcopy>::new()
+            .buffers(&src1, &mut dst1)
+            .unwrap()
+            .chain_to(&mut second)
+            .build()
+            .unwrap();
+        mock.exec_one(&mut first).unwrap().wait().unwrap();
+
+        let captured = mock.captured();
+        assert_eq!(captured.len(), 2);
+        assert_eq!(captured[0].control0 & (1 << 2 | 1 << 3), 1 << 2);
+        assert_eq!(captured[0].next, next);
+        assert_eq!(captured[1].control0 & (1 << 2 | 1 << 3), 0);
+    }
+
+    #[test]
+    fn chain_continuous_runs_the_following_packet() {
+        let mock = MockExecutor::new();
+        let (src1, mut dst1) = ([1u8; 16], [0u8; 16]);
+        let (src2, mut dst2) = ([2u8; 16], [0u8; 16]);
+        let first = PacketBuilder::<Memcopy>::new()
+            .buffers(&src1, &mut dst1)
+            .unwrap();
+        let mut packets: [ControlPacket; 2] = [
+            unsafe { first.chain_continuous() }.build().unwrap(),
+            PacketBuilder::<Memcopy>::new()
+                .buffers(&src2, &mut dst2)
+                .unwrap()
+                .decr_semaphore()
+                .build()
+                .unwrap(),
+        ];
+        mock.exec_one(&mut packets[0]).unwrap().wait().unwrap();
+
+        let captured = mock.captured();
+        assert_eq!(captured.len(), 2);
+        assert_eq!(captured[0].control0 & (1 << 2 | 1 << 3), 1 << 3);
+        assert_eq!(captured[1].control0 & (1 << 2 | 1 << 3), 0);
+    }
+
+    #[test]
+    fn dest_checked_compares_with_the_source() {
+        let src = [0u8; 16];
+        let mut short = [0u8; 8];
+        let mut long = [0u8; 32];
+        let res = PacketBuilder::<Memcopy>::new()
+            .input(&src)
+            .dest_checked(&mut long);
+        assert_eq!(res.err(), Some(BufferError::SourceTooShort { src: 16, len: 32 }));
+
+        let captured = run!(PacketBuilder::<Memcopy>::new()
+            .input(&src)
+            .dest_checked(&mut short)
+            .unwrap()
+            .decr_semaphore()
+            .build()
+            .unwrap());
+        assert_eq!(captured.bufsize, 8);
+    }
+
+    #[test]
+    fn build_checks_a_source_set_after_dest_checked() {
+        let src = [0u8; 16];
+        let mut long = [0u8; 32];
+        let res = PacketBuilder::<Memcopy>::new()
+            .dest_checked(&mut long)
+            .unwrap()
+            .input(&src)
+            .decr_semaphore()
+            .build();
+        assert_eq!(res.err(), Some(BufferError::SourceTooShort { src: 16, len: 32 }));
+    }
+
+    #[test]
+    fn blit_fills_a_rectangle() {
+        let mut fb = [0u8; 32 * 32];
+        {
+            let mock = MockExecutor::new();
+            mock.set_emulate(true);
+            let window = Framebuffer::window(&mut fb[8 * 32 + 8..], 32, 16, 16).unwrap();
+            let mut packet: ControlPacket = PacketBuilder::<Blit>::new()
+                .constant(0x00FF_00FF)
+                .framebuffer(window)
+                .decr_semaphore()
+                .build()
+                .unwrap();
+            mock.exec_one(&mut packet).unwrap().wait().unwrap();
+            let captured = mock.captured()[0];
+            assert_ne!(captured.control0 & 1 << 16, 0);
+            assert_eq!(captured.source, 0x00FF_00FF);
+            assert_eq!(captured.control1, 32);
+        }
+        for (y, line) in fb.chunks(32).enumerate() {
+            for (x, &byte) in line.iter().enumerate() {
+                let inside = (8..24).contains(&x) && (8..24).contains(&y);
+                let expected = if inside { [0xFF, 0x00][x % 2] } else { 0 };
+                assert_eq!(byte, expected, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn input_after_dest_keeps_the_destination_length() {
+        let src = [0u8; 16];
+        let mut short = [0u8; 8];
+        let captured = run!(PacketBuilder::<Memcopy>::new()
+            .dest(&mut short)
+            .input(&src)
+            .decr_semaphore()
+            .build()
+            .unwrap());
+        assert_eq!(captured.bufsize, 8);
+
+        let mut fb = [0u8; 64];
+        let res = PacketBuilder::<Blit>::new()
+            .framebuffer(Framebuffer::new(&mut fb, 8).unwrap())
+            .input(&src)
+            .decr_semaphore()
+            .build();
+        assert_eq!(res.err(), Some(BufferError::SourceTooShort { src: 16, len: 64 }));
+
+        let src = [0u8; 64];
+        let captured = run!(PacketBuilder::<Blit>::new()
+            .framebuffer(Framebuffer::new(&mut fb, 16).unwrap())
+            .input(&src)
+            .decr_semaphore()
+            .build()
+            .unwrap());
+        assert_eq!(captured.bufsize, 16 | 4 << 16);
+    }
+}
@@ -0,0 +1,131 @@
+//! Vertically flipped blits.
+//!
+//! The DCP writes the lines of a blit top to bottom, the stride is unsigned and the source is read
+//! front to back, so a blit packet can't mirror the image. A [`FlippedBlit`] flips it
+//! vertically with one memcopy packet per line instead, reading the source from its last line.
+//! Horizontal flips can't be done by the DCP at all, the bytes of a line are always copied in
+//! order.
+
+use core::array;
+
+use super::{
+    builder::{BlitError, Framebuffer, PacketBuilder},
+    ControlPacket,
+};
+use crate::{
+    ex::{ExError, Executor, Task},
+    ops::Memcopy,
+};
+
+/// Copies `N` lines to a framebuffer upside down, one packet per line.
+pub struct FlippedBlit<'a, const N: usize> {
+    packets: [ControlPacket<'a>; N],
+}
+
+impl<'a, const N: usize> FlippedBlit<'a, N> {
+    /// Copies `src`, `N` contiguous lines of `fb.width()` bytes, to `fb` with the first line at
+    /// the bottom.
+    ///
+    /// Returns [`LineCountMismatch`](BlitError::LineCountMismatch) if `fb` doesn't have `N`
+    /// lines, [`BufferTooSmall`](BlitError::BufferTooSmall) if `src` is too short.
+    pub fn new(src: &'a [u8], fb: Framebuffer<'a>) -> Result<Self, BlitError> {
+        if fb.height() as usize != N {
+            return Err(BlitError::LineCountMismatch {
+                expected: N,
+                got: fb.height(),
+            });
+        }
+        let (width, stride) = (fb.width() as usize, fb.stride());
+        let needed = width * N;
+        if src.len() < needed {
+            return Err(BlitError::BufferTooSmall {
+                needed,
+                got: src.len(),
+            });
+        }
+        // Framebuffer checked that the window fits
+        let window = (N - 1) * stride + width;
+        let mut src_lines = src.chunks(width);
+        let mut dst_lines = fb.buf[..window].chunks_mut(stride).rev();
+        let packets = array::from_fn(|i| {
+            let builder = PacketBuilder::<Memcopy>::new().buffers_unchecked(
+                src_lines.next().unwrap(),
+                &mut dst_lines.next().unwrap()[..width],
+            );
+            if i == N - 1 {
+                builder.decr_semaphore().into()
+            } else {
+                builder.into()
+            }
+        });
+        Ok(Self { packets })
+    }
+
+    /// Runs the packets as a single task.
+    pub fn exec<E: Executor>(&'a mut self, ex: &'a E) -> Result<Task<'a>, ExError> {
+        ex.exec_slice(&mut self.packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockExecutor;
+
+    /// Checkerboard with a different dark square on every line.
+    fn pixel(x: usize, y: usize) -> u8 {
+        if (x + y) & 1 == 0 {
+            0xFF
+        } else {
+            y as u8
+        }
+    }
+
+    #[test]
+    fn lines_land_upside_down() {
+        let src: [u8; 16] = array::from_fn(|i| pixel(i % 4, i / 4));
+        let mut fb = [0u8; 8 * 6];
+        {
+            let mock = MockExecutor::new();
+            mock.set_emulate(true);
+            let window = Framebuffer::window(&mut fb[8 + 2..], 8, 4, 4).unwrap();
+            let mut blit = FlippedBlit::<4>::new(&src, window).unwrap();
+            blit.exec(&mock).unwrap().wait().unwrap();
+            assert_eq!(mock.captured().len(), 4);
+        }
+        for (y, line) in fb.chunks(8).enumerate() {
+            for (x, &byte) in line.iter().enumerate() {
+                let expected = match (x, y) {
+                    (2..=5, 1..=4) => pixel(x - 2, 4 - y),
+                    _ => 0,
+                };
+                assert_eq!(byte, expected, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn sizes_are_checked() {
+        let src = [0u8; 16];
+        let mut fb = [0u8; 16];
+        let window = Framebuffer::new(&mut fb, 4).unwrap();
+        let res = FlippedBlit::<3>::new(&src, window);
+        assert_eq!(
+            res.err(),
+            Some(BlitError::LineCountMismatch {
+                expected: 3,
+                got: 4
+            })
+        );
+
+        let window = Framebuffer::new(&mut fb, 4).unwrap();
+        let res = FlippedBlit::<4>::new(&src[..15], window);
+        assert_eq!(
+            res.err(),
+            Some(BlitError::BufferTooSmall {
+                needed: 16,
+                got: 15
+            })
+        );
+    }
+}
@@ -1,7 +1,9 @@
-use crate::Error;
+use crate::{DcpErrorCode, Error};
 use core::marker::PhantomData;
 
 pub mod builder;
+pub mod flip;
+pub mod pipeline;
 
 /// The struct that is passed to the DCP.
 #[derive(Debug)]
@@ -18,6 +20,311 @@ pub struct ControlPacket<'a> {
     _lifetime: PhantomData<&'a ()>,
 }
 
+// The DCP reads the packet as eight 32 bit words, the status word being the last one.
+#[cfg(target_pointer_width = "32")]
+const _: () = {
+    use core::mem::{offset_of, size_of};
+    assert!(size_of::<ControlPacket>() == 32);
+    assert!(offset_of!(ControlPacket, next) == 0);
+    assert!(offset_of!(ControlPacket, control0) == 4);
+    assert!(offset_of!(ControlPacket, control1) == 8);
+    assert!(offset_of!(ControlPacket, source) == 12);
+    assert!(offset_of!(ControlPacket, dest) == 16);
+    assert!(offset_of!(ControlPacket, bufsize) == 20);
+    assert!(offset_of!(ControlPacket, payload) == 24);
+    assert!(offset_of!(ControlPacket, status) == 28);
+};
+const _: () = assert!(core::mem::size_of::<Control0>() == 4);
+const _: () = assert!(core::mem::size_of::<Control1>() == 4);
+const _: () = assert!(core::mem::size_of::<Status>() == 4);
+
+impl<'a> ControlPacket<'a> {
+    /// Number of bytes of the payload used by the operation.
+    ///
+    /// The payload holds the key, then the IV, then the hash output.
+    pub(crate) fn payload_len(&self) -> usize {
+        let ctl0 = self.control0;
+        let mut len = 0;
+        if ctl0.has(Control0Flag::EnableCipher) {
+            if ctl0.has(Control0Flag::PayloadKey) {
+                len += 16;
+            }
+            if ctl0.has(Control0Flag::CipherInit) {
+                len += 16;
+            }
+        }
+        if ctl0.has(Control0Flag::EnableHash)
+            && (ctl0.has(Control0Flag::HashTerm) || ctl0.has(Control0Flag::HashCheck))
+        {
+            len += unsafe { self.control1.crypto.hash }.output_len();
+        }
+        len
+    }
+
+    /// The digest written to the payload by a packet terminating a hash.
+    pub(crate) fn hash_output(&self) -> Option<&[u8]> {
+        let ctl0 = self.control0;
+        if self.payload.is_null()
+            || !ctl0.has(Control0Flag::EnableHash)
+            || !ctl0.has(Control0Flag::HashTerm)
+        {
+            return None;
+        }
+        let len = unsafe { self.control1.crypto.hash }.output_len();
+        let offset = self.payload_len() - len;
+        Some(unsafe { core::slice::from_raw_parts(self.payload.add(offset), len) })
+    }
+
+    /// Same as [`hash_output`](Self::hash_output), to write the digest like the DCP does.
+    #[cfg(any(test, feature = "std"))]
+    pub(crate) fn hash_output_mut(&mut self) -> Option<&mut [u8]> {
+        let len = self.hash_output()?.len();
+        let offset = self.payload_len() - len;
+        Some(unsafe { core::slice::from_raw_parts_mut(self.payload.add(offset), len) })
+    }
+
+    /// Number of bytes processed by the operation.
+    pub(crate) fn data_len(&self) -> usize {
+        if self.control0.has(Control0Flag::EnableBlit) {
+            let blit = unsafe { self.bufsize.blit };
+            blit.width as usize * blit.height as usize
+        } else {
+            unsafe { self.bufsize.buf as usize }
+        }
+    }
+
+    /// The hash algorithm selected in the packet.
+    pub(crate) fn hash(&self) -> Hash {
+        unsafe { self.control1.crypto.hash }
+    }
+
+    /// Returns the next packet the DCP will load after this one, if any.
+    ///
+    /// # Safety
+    ///
+    /// The packets in the chain must still be alive.
+    pub(crate) unsafe fn next_in_chain(&self) -> Option<&ControlPacket<'a>> {
+        if self.control0.has(Control0Flag::ChainContinuous) {
+            Some(&*(self as *const Self).add(1))
+        } else if self.control0.has(Control0Flag::Chain) {
+            self.next.as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// Mutable version of [`next_in_chain`](Self::next_in_chain).
+    ///
+    /// # Safety
+    ///
+    /// The packets in the chain must still be alive.
+    #[cfg(any(test, feature = "std"))]
+    pub(crate) unsafe fn next_in_chain_mut(&mut self) -> Option<&mut ControlPacket<'a>> {
+        if self.control0.has(Control0Flag::ChainContinuous) {
+            Some(&mut *(self as *mut Self).add(1))
+        } else if self.control0.has(Control0Flag::Chain) {
+            self.next.as_mut()
+        } else {
+            None
+        }
+    }
+
+    /// Copies the fields of the packet.
+    #[cfg(any(test, feature = "std"))]
+    pub(crate) fn capture(&self) -> crate::mock::CapturedPacket {
+        crate::mock::CapturedPacket {
+            next: self.next as usize,
+            control0: unsafe { *(&self.control0 as *const Control0 as *const u32) },
+            control1: unsafe { *(&self.control1 as *const Control1 as *const u32) },
+            source: unsafe { self.source.pointer } as usize,
+            dest: self.dest as usize,
+            bufsize: unsafe { self.bufsize.buf },
+            payload: self.payload as usize,
+            tag: self.control0.tag,
+        }
+    }
+
+    /// Iterates over this packet and the ones chained to it, in execution order.
+    ///
+    /// # Safety
+    ///
+    /// The packets in the chain must still be alive.
+    pub(crate) unsafe fn chain(&self) -> impl Iterator<Item = &ControlPacket<'a>> {
+        core::iter::successors(Some(self), |packet| packet.next_in_chain())
+    }
+
+    /// Panics if a `next` pointer is set without the chain flag or the other way around, or it is
+    /// not word aligned.
+    ///
+    /// The packets are behind references, so only the `next` pointers can be misaligned. Each one
+    /// is checked before following it.
+    ///
+    /// # Safety
+    ///
+    /// The packets in the chain must still be alive.
+    #[cfg(debug_assertions)]
+    pub(crate) unsafe fn check_links(&self) {
+        for packet in self.chain() {
+            let chained = packet.control0.has(Control0Flag::Chain);
+            assert!(
+                chained != packet.next.is_null(),
+                "packet at {:p} has the chain flag {} but its next pointer is {:p}",
+                packet,
+                if chained { "set" } else { "clear" },
+                packet.next,
+            );
+            assert!(
+                packet.next as usize & 0x3 == 0,
+                "packet at {:p} is chained to a misaligned packet at {:p}",
+                packet,
+                packet.next,
+            );
+        }
+    }
+
+    /// Logs the packets of the chain at the trace level before they are handed to `channel`.
+    ///
+    /// The payload pointer of a packet with a payload key is replaced by `<redacted>`, so the
+    /// logs don't tell where to find the key.
+    ///
+    /// # Safety
+    ///
+    /// The packets in the chain must still be alive.
+    #[cfg(feature = "trace")]
+    pub(crate) unsafe fn trace(&self, channel: u8) {
+        use core::fmt::Debug;
+
+        for packet in self.chain() {
+            let ctl0 = packet.control0;
+            // Only read the union as what the operation uses, the crypto fields are enums
+            let (stride, crypto);
+            let control1: &dyn Debug = if ctl0.has(Control0Flag::EnableBlit) {
+                stride = packet.control1.blit_stride;
+                &stride
+            } else if ctl0.has(Control0Flag::EnableCipher) || ctl0.has(Control0Flag::EnableHash) {
+                crypto = packet.control1.crypto;
+                &crypto
+            } else {
+                &"unused"
+            };
+            let payload: &dyn Debug = if ctl0.has(Control0Flag::PayloadKey) {
+                &"<redacted>"
+            } else {
+                &packet.payload
+            };
+            log::trace!(
+                "CH{} packet {:p}: next {:p}, {:?}, tag {}, control1 {:?}, {:?}, dest {:p}, {:?}, \
+                 payload {:?}",
+                channel,
+                packet,
+                packet.next,
+                Flags(ctl0),
+                ctl0.tag,
+                control1,
+                packet.source,
+                packet.dest,
+                packet.bufsize,
+                payload,
+            );
+        }
+    }
+
+    /// Checks that only the last packet of the chain decrements the channel semaphore.
+    ///
+    /// Decrementing it earlier stops the channel before the end of the chain.
+    ///
+    /// # Safety
+    ///
+    /// The packets in the chain must still be alive.
+    pub(crate) unsafe fn valid_chain(&self) -> bool {
+        self.chain().all(|packet| {
+            let last = packet.next_in_chain().is_none();
+            packet.control0.has(Control0Flag::DecrSemaphore) == last
+        })
+    }
+}
+
+#[cfg(feature = "cortex-m")]
+impl<'a> ControlPacket<'a> {
+    /// Memory region read through the source pointer as `(address, length)`.
+    pub(crate) fn source_region(&self) -> Option<(usize, usize)> {
+        if self.control0.has(Control0Flag::ConstantFill) {
+            return None;
+        }
+        let ptr = unsafe { self.source.pointer };
+        (!ptr.is_null()).then(|| (ptr as usize, self.data_len()))
+    }
+
+    /// Memory region written through the destination pointer as `(address, length)`.
+    pub(crate) fn dest_region(&self) -> Option<(usize, usize)> {
+        let ctl0 = self.control0;
+        let writes = ctl0.has(Control0Flag::EnableMemcopy)
+            || ctl0.has(Control0Flag::EnableCipher)
+            || ctl0.has(Control0Flag::EnableBlit);
+        let len = if ctl0.has(Control0Flag::EnableBlit) {
+            // The lines are spread over the framebuffer
+            let blit = unsafe { self.bufsize.blit };
+            let stride = unsafe { self.control1.blit_stride } as usize;
+            match blit.height {
+                0 => 0,
+                h => (h as usize - 1) * stride + blit.width as usize,
+            }
+        } else {
+            self.data_len()
+        };
+        (writes && !self.dest.is_null()).then_some((self.dest as usize, len))
+    }
+
+    /// Memory region of the payload as `(address, length)`.
+    pub(crate) fn payload_region(&self) -> Option<(usize, usize)> {
+        (!self.payload.is_null()).then(|| (self.payload as usize, self.payload_len()))
+    }
+}
+
+/// A [`ControlPacket`] that can be moved to or shared with another execution context, like an
+/// interrupt handler.
+///
+/// Packets hold raw pointers to their buffers and the next packet of the chain, so they are
+/// neither `Send` nor `Sync`. The buffers are borrowed for `'a` by the builder, the wrapper only
+/// promises that the packet is not accessed from two contexts at once.
+#[repr(transparent)]
+pub struct SyncPacket<'a>(ControlPacket<'a>);
+
+// The raw pointers come from borrows held for 'a, which would be Send and Sync themselves
+unsafe impl Send for SyncPacket<'_> {}
+unsafe impl Sync for SyncPacket<'_> {}
+
+impl<'a> SyncPacket<'a> {
+    /// Wraps a packet.
+    ///
+    /// # Safety
+    ///
+    /// The buffers and chained packets must be valid for `'a` and the packet must only be
+    /// modified or submitted from one context at a time (e.g. behind a critical section).
+    pub const unsafe fn new(packet: ControlPacket<'a>) -> Self {
+        Self(packet)
+    }
+
+    /// Returns the wrapped packet.
+    pub fn into_inner(self) -> ControlPacket<'a> {
+        self.0
+    }
+}
+
+impl<'a> core::ops::Deref for SyncPacket<'a> {
+    type Target = ControlPacket<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl core::ops::DerefMut for SyncPacket<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
 /// The Control0 field of the control packet.   
 /// It controls the main functions of the DCP and has a tag to identify packets.
 #[repr(C)]
@@ -58,12 +365,66 @@ pub(crate) enum Control0Flag {
     OutputWordSwap = 1 << 23,
 }
 
+#[cfg(feature = "trace")]
+impl Control0Flag {
+    const ALL: [Control0Flag; 24] = [
+        Control0Flag::InterruptEnable,
+        Control0Flag::DecrSemaphore,
+        Control0Flag::Chain,
+        Control0Flag::ChainContinuous,
+        Control0Flag::EnableMemcopy,
+        Control0Flag::EnableCipher,
+        Control0Flag::EnableHash,
+        Control0Flag::EnableBlit,
+        Control0Flag::CipherEncrypt,
+        Control0Flag::CipherInit,
+        Control0Flag::OtpKey,
+        Control0Flag::PayloadKey,
+        Control0Flag::HashInit,
+        Control0Flag::HashTerm,
+        Control0Flag::HashCheck,
+        Control0Flag::HashOutput,
+        Control0Flag::ConstantFill,
+        Control0Flag::TestSemaIRQ,
+        Control0Flag::KeyByteSwap,
+        Control0Flag::KeyWordSwap,
+        Control0Flag::InputByteSwap,
+        Control0Flag::InputWordSwap,
+        Control0Flag::OutputByteSwap,
+        Control0Flag::OutputWordSwap,
+    ];
+}
+
+/// Lists the flags set in a [`Control0`].
+#[cfg(feature = "trace")]
+struct Flags(Control0);
+
+#[cfg(feature = "trace")]
+impl core::fmt::Debug for Flags {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let set = Control0Flag::ALL.iter().filter(|&&flag| self.0.has(flag));
+        f.debug_list().entries(set).finish()
+    }
+}
+
 impl Control0 {
     pub(crate) fn flag(mut self, flag: Control0Flag) -> Self {
         let ptr = &mut self as *mut Self as *mut u32;
         unsafe { *ptr |= flag as u32 };
         self
     }
+
+    pub(crate) fn unflag(mut self, flag: Control0Flag) -> Self {
+        let ptr = &mut self as *mut Self as *mut u32;
+        unsafe { *ptr &= !(flag as u32) };
+        self
+    }
+
+    /// Checks if a flag is set.
+    pub(crate) fn has(self, flag: Control0Flag) -> bool {
+        let ptr = &self as *const Self as *const u32;
+        unsafe { *ptr & flag as u32 != 0 }
+    }
 }
 
 /// The Control1 field contains values used in encrypt, hash or blit operations.
@@ -72,40 +433,76 @@ impl Control0 {
 union Control1 {
     /// Crypto config
     pub crypto: Ctl1Crypto,
-    /// Total framebuffer lenght
-    pub blit_size: u16,
+    /// Distance in bytes between the lines of the blit destination
+    pub blit_stride: u32,
 }
 
 impl core::fmt::Debug for Control1 {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        let blit = unsafe { self.blit_size };
+        let blit = unsafe { self.blit_stride };
         let crypto = unsafe { self.crypto };
         f.write_fmt(format_args!(
-            "Control1 {blit} bytes or {crypto:#?}"
+            "Control1 {blit} bytes stride or {crypto:#?}"
         ))
     }
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for Control1 {
+    fn format(&self, f: defmt::Formatter) {
+        let blit = unsafe { self.blit_stride };
+        let crypto = unsafe { self.crypto };
+        defmt::write!(f, "Control1 {} bytes stride or {}", blit, crypto)
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 struct Ctl1Crypto {
     cipher: Cipher,
     key: KeySelect,
     hash: Hash,
-    // doesn't seem to be used anywhere
-    _cipher_config: u8,
+    /// Optional cipher configuration, no values are documented for the i.MX RT.
+    cipher_config: u8,
+}
+
+/// Raw value that doesn't match any variant of the enum it's converted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UnknownValue(pub u8);
+
+/// Implements `TryFrom<u8>` for a `#[repr(u8)]` enum, matching the variant discriminants.
+macro_rules! try_from_u8 {
+    ( $ty:ident { $( $variant:ident ),* $(,)? } ) => {
+        impl core::convert::TryFrom<u8> for $ty {
+            type Error = UnknownValue;
+
+            fn try_from(raw: u8) -> Result<Self, Self::Error> {
+                match raw {
+                    $( x if x == $ty::$variant as u8 => Ok($ty::$variant), )*
+                    _ => Err(UnknownValue(raw)),
+                }
+            }
+        }
+    };
 }
 
 /// Supported symmetric ciphers
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum Cipher {
     Aes128Ecb = 0,
-    Aes128Cbc = 1 << 3,
+    /// The cipher mode is in the high nibble, the algorithm in the low one.
+    Aes128Cbc = 1 << 4,
 }
 
+try_from_u8!(Cipher { Aes128Ecb, Aes128Cbc });
+
 /// Select key to use from a keyslot
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum KeySelect {
     Key0 = 0x0,
@@ -116,15 +513,39 @@ pub enum KeySelect {
     OtpKey = 0xFF,
 }
 
+try_from_u8!(KeySelect { Key0, Key1, Key2, Key3, UniqueKey, OtpKey });
+
 /// Supported hashing algorithms
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum Hash {
+    /// SHA-1, the digest is written to the payload with its bytes in reverse order.
+    ///
+    /// [`HashSelect::output`](crate::ops::HashSelect::output) converts it to the standard order,
+    /// the one printed by `sha1sum`.
     Sha1 = 0,
+    /// CRC-32/MPEG-2 (polynomial `0x04C11DB7`, init `0xFFFFFFFF`, not reflected, no final XOR),
+    /// written to the payload in little endian order.
     Crc32 = 1,
+    /// SHA-256, the digest is written to the payload with its bytes in reverse order like
+    /// [`Sha1`](Self::Sha1).
     Sha256 = 2,
 }
 
+try_from_u8!(Hash { Sha1, Crc32, Sha256 });
+
+impl Hash {
+    /// Size in bytes of the digest written to the payload.
+    pub const fn output_len(self) -> usize {
+        match self {
+            Hash::Sha1 => 20,
+            Hash::Crc32 => 4,
+            Hash::Sha256 => 32,
+        }
+    }
+}
+
 /// Data source for the DCP.
 ///
 /// It can either be a 32 bit value for constant fill or a pointer.
@@ -136,6 +557,23 @@ pub union Source<'a> {
     _lifetime: PhantomData<&'a ()>
 }
 
+impl<'a> Source<'a> {
+    /// Points the DCP at the bytes of `slice`.
+    ///
+    /// Use [`core::mem::size_of_val`] for the length of the buffer, not `slice.len()`.
+    pub fn from_slice<T>(slice: &'a [T]) -> Self {
+        Source {
+            pointer: slice.as_ptr().cast(),
+        }
+    }
+}
+
+impl<'a, T> From<&'a [T]> for Source<'a> {
+    fn from(slice: &'a [T]) -> Self {
+        Self::from_slice(slice)
+    }
+}
+
 impl core::fmt::Debug for Source<'_> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.write_fmt(format_args!(
@@ -144,6 +582,13 @@ impl core::fmt::Debug for Source<'_> {
     }
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for Source<'_> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Source {=u32:#x}", unsafe { self.constant })
+    }
+}
+
 /// Holds the buffer size or the blit framebuffer's height and width.
 #[derive(Clone, Copy)]
 #[repr(C)]
@@ -152,6 +597,13 @@ union BufSize {
     pub blit: BlitSize,
 }
 
+impl BufSize {
+    /// Size of a buffer of `len` bytes.
+    fn of_len(len: usize) -> Self {
+        BufSize { buf: len as u32 }
+    }
+}
+
 impl core::fmt::Debug for BufSize {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.write_fmt(format_args!(
@@ -160,6 +612,13 @@ impl core::fmt::Debug for BufSize {
     }
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for BufSize {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "BufSize ({=u32})", unsafe { self.buf })
+    }
+}
+
 /// Holds the blit framebuffer size data.
 #[derive(Clone, Copy)]
 #[repr(C)]
@@ -172,32 +631,124 @@ pub struct BlitSize {
 
 /// Is filled by the DCP at the end of the operation, holds eventual errors and the packet tag.
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 pub struct Status {
     /// Completion or eventual errors.
     pub bits: u8,
-    _pad: u8,
+    /// Unused by the hardware, set while the packet waits in a software queue or once it was
+    /// dropped from it.
+    pub(crate) queued: u8,
     pub error_code: u8,
     /// Tag initially put in Control0 for identification.
     pub tag: u8,
 }
 
 impl Status {
+    /// Builds the status of a completed packet from the value of a channel status register.
+    ///
+    /// The register has the same layout as the status word, without the completion bit.
+    pub(crate) fn from_channel(raw: u32) -> Self {
+        Self {
+            bits: raw as u8 & 0b1111110 | 1,
+            queued: 0,
+            error_code: (raw >> 16) as u8,
+            tag: (raw >> 24) as u8,
+        }
+    }
+
+    /// Tag of the packet, copied from Control0 by the DCP.
+    pub fn tag(&self) -> u8 {
+        self.tag
+    }
+
+    /// Checks if the DCP is done with the packet, whether it failed or not.
+    pub fn is_complete(&self) -> bool {
+        self.bits & 1 != 0
+    }
+
+    /// Error code reported by the DCP, zero if the packet completed successfully.
+    ///
+    /// See [`DcpErrorCode`] for the meaning of the codes.
+    pub fn error_code(&self) -> u8 {
+        self.error_code
+    }
+
     /// Non-blocking API to poll for completion.  
     /// Returns WouldBlock when the operation is not complete
     pub fn poll(&self) -> crate::Result {
-        if self.bits & 1 == 1 {
-            match self.bits {
-                1 => Ok(self.tag),
-                2 => Err(nb::Error::Other(Error::HashMismatch(self.error_code))),
-                4 => Err(nb::Error::Other(Error::SetupError(self.error_code))),
-                8 => Err(nb::Error::Other(Error::PacketError(self.error_code))),
-                16 => Err(nb::Error::Other(Error::SourceError(self.error_code))),
-                32 => Err(nb::Error::Other(Error::DestError(self.error_code))),
-                _ => Err(nb::Error::Other(Error::Other(self.error_code))),
-            }
+        // The completion bit is set together with the error bits
+        if self.bits & 1 == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        if self.bits & 0b1111110 == 0 {
+            return Ok(self.tag);
+        }
+        let code = DcpErrorCode::new(self.bits, self.error_code);
+        let err = if code.hash_mismatch() {
+            Error::HashMismatch(code)
+        } else if code.setup_error() {
+            Error::SetupError(code)
+        } else if code.packet_error() {
+            Error::PacketError(code)
+        } else if code.bus_error_source() {
+            Error::SourceError(code)
+        } else if code.bus_error_dest() {
+            Error::DestError(code)
+        } else if code.page_fault() {
+            Error::PageFault(code)
         } else {
-            Err(nb::Error::WouldBlock)
+            Error::Other(code)
+        };
+        Err(nb::Error::Other(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(bits: u8) -> Status {
+        Status { bits, queued: 0, error_code: 0, tag: 7 }
+    }
+
+    #[test]
+    fn poll_pending() {
+        assert!(matches!(status(0).poll(), Err(nb::Error::WouldBlock)));
+    }
+
+    #[test]
+    fn poll_complete() {
+        assert!(matches!(status(1).poll(), Ok(7)));
+    }
+
+    /// Flag bit each error variant is decoded from.
+    fn error_bit(e: &Error) -> u8 {
+        match e {
+            Error::HashMismatch(_) => 1 << 1,
+            Error::SetupError(_) => 1 << 2,
+            Error::PacketError(_) => 1 << 3,
+            Error::SourceError(_) => 1 << 4,
+            Error::DestError(_) => 1 << 5,
+            Error::PageFault(_) => 1 << 6,
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn poll_error_bits() {
+        for bit in (1..7).map(|n| 1u8 << n) {
+            match status(bit | 1).poll() {
+                Err(nb::Error::Other(e)) => assert_eq!(error_bit(&e), bit, "{:?}", e),
+                r => panic!("{:#04x} polled as {:?}", bit | 1, r),
+            }
         }
     }
+
+    #[test]
+    fn from_channel_keeps_page_fault() {
+        let status = Status::from_channel(1 << 6 | 0x2a << 24);
+        assert_eq!(status.tag(), 0x2a);
+        assert!(matches!(status.poll(), Err(nb::Error::Other(Error::PageFault(_)))));
+    }
 }
@@ -0,0 +1,79 @@
+//! Cipher and hash operations run as two chained packets.
+//!
+//! The fused [`CipherHash`](crate::ops::CipherHash) operation hashes the data while it goes
+//! through the cipher. A [`Pipeline`] instead encrypts (or decrypts) into a buffer with a first
+//! packet, then hashes that buffer with a second one, e.g. for encrypt-then-MAC.
+
+use super::{
+    builder::{BufferError, HasSource, NoDest, NoSource, PacketBuilder},
+    ControlPacket,
+};
+use crate::{
+    ex::{ExError, Executor, Task},
+    ops::{Cipher, Hash},
+};
+
+/// A cipher packet followed by a packet hashing its output.
+pub struct Pipeline<'a> {
+    packets: [ControlPacket<'a>; 2],
+}
+
+impl<'a> Pipeline<'a> {
+    /// Chains `cipher`, writing its output to `out`, to `hash`, reading it back from `out`.
+    ///
+    /// `out` is both the destination of the cipher packet and the source of the hash one, so it
+    /// stays borrowed as long as the pipeline. The hash packet decrements the semaphore, the
+    /// cipher one must not.
+    ///
+    /// Returns an error if a packet fails to build.
+    pub fn new(
+        cipher: PacketBuilder<'a, Cipher, HasSource, NoDest>,
+        hash: PacketBuilder<'a, Hash, NoSource, NoDest>,
+        out: &'a mut [u8],
+    ) -> Result<Self, BufferError> {
+        let (ptr, len) = (out.as_mut_ptr(), out.len());
+        // `out` is borrowed for 'a. Only raw pointers to it are handed out, a shared and a
+        // mutable slice of it would alias
+        let (cipher, hash) = unsafe { (cipher.dest_raw(ptr, len), hash.input_raw(ptr, len)) };
+        Ok(Self {
+            packets: [cipher.build()?, hash.decr_semaphore().build()?],
+        })
+    }
+
+    /// Runs both packets as a single task.
+    ///
+    /// The hash is in the payload of the hash packet once the task completes.
+    pub fn exec<E: Executor>(&'a mut self, ex: &'a E) -> Result<Task<'a>, ExError> {
+        ex.exec_slice(&mut self.packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock::MockExecutor, ops::Sha256, packet::Source};
+
+    #[test]
+    fn hash_reads_the_cipher_output() {
+        let input = [0u8; 32];
+        let mut out = [0u8; 32];
+        let mut digest = [0u8; 32];
+        let out_addr = out.as_ptr() as usize;
+        let cipher = PacketBuilder::<Cipher>::new()
+            .otp_key()
+            .encrypt()
+            .source(Source::from_slice(&input));
+        let hash = PacketBuilder::<Hash>::new()
+            .hash_init()
+            .hash_term()
+            .hash_payload::<Sha256, 32>(&mut digest);
+        let mut pipeline = Pipeline::new(cipher, hash, &mut out).unwrap();
+
+        let mock = MockExecutor::new();
+        drop(pipeline.exec(&mock).unwrap());
+        let captured = mock.captured();
+        assert_eq!(captured.len(), 2);
+        assert_eq!((captured[0].dest, captured[1].source), (out_addr, out_addr));
+        assert_eq!((captured[0].bufsize, captured[1].bufsize), (32, 32));
+    }
+}
@@ -0,0 +1,42 @@
+//! Digest comparison for verification done in software.
+//!
+//! Comparing a MAC with `==` stops at the first byte that differs, so the time it takes tells an
+//! attacker how much of a forged MAC is right. The functions here always look at every byte.
+//! Hashes checked with [`verify_hash`](crate::hash::verify_hash) are compared by the DCP instead.
+
+use crate::ops::HashSelect;
+
+/// Checks if `a` and `b` are equal, in a time that only depends on their length.
+///
+/// Slices of different lengths are never equal, their lengths are not treated as secret.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    // The accumulator goes through black_box at every step, so the compiler can't tell when it
+    // stops changing and turn the fold into an early exit
+    let diff = a
+        .iter()
+        .zip(b)
+        .fold(0, |acc, (x, y)| core::hint::black_box(acc | (x ^ y)));
+    diff == 0
+}
+
+/// Checks if two digests computed with `H` are equal, see [`ct_eq`].
+pub fn digest_eq<H: HashSelect>(a: &H::Output, b: &H::Output) -> bool {
+    ct_eq(H::raw(a).as_ref(), H::raw(b).as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ct_eq_checks_every_byte() {
+        assert!(ct_eq(b"", b""));
+        assert!(ct_eq(&[1, 2, 3], &[1, 2, 3]));
+        assert!(!ct_eq(&[0xFF, 2, 3], &[0, 2, 3]));
+        assert!(!ct_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!ct_eq(&[1, 2], &[1, 2, 3]));
+    }
+}
@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+use teensy4_bsp as bsp;
+use teensy4_panic as _;
+
+use cortex_m::{asm, delay::Delay, peripheral::syst::SystClkSource};
+use imxrt_dcp::{
+    ex::SingleChannel,
+    ops::Cipher,
+    packet::{ControlPacket, KeySelect},
+    prelude::*,
+};
+use teensy40_examples::logging;
+
+// AES-128 CBC test vectors from NIST SP 800-38A, F.2.2
+const KEY: [u8; 16] = [
+    0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c,
+];
+const IV: [u8; 16] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+];
+const CIPHERTEXT: [u8; 32] = [
+    0x76, 0x49, 0xab, 0xac, 0x81, 0x19, 0xb2, 0x46, 0xce, 0xe9, 0x8e, 0x9b, 0x12, 0xe9, 0x19, 0x7d,
+    0x50, 0x86, 0xcb, 0x9b, 0x50, 0x72, 0x19, 0xee, 0x95, 0xdb, 0x11, 0x3a, 0x91, 0x76, 0x78, 0xb2,
+];
+const PLAINTEXT: [u8; 32] = [
+    0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a,
+    0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac, 0x45, 0xaf, 0x8e, 0x51,
+];
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let ip = bsp::Peripherals::take().unwrap();
+    let mut delay = Delay::with_source(cp.SYST, bsp::EXT_SYSTICK_HZ, SystClkSource::External);
+    let mut ccm = ip.ccm.handle;
+
+    logging::init().unwrap();
+    delay.delay_ms(2000);
+
+    let dcp = dcp::Unclocked::take().unwrap().init(ccm.raw().0);
+    // Load the key once, every cipher packet can then refer to the slot
+    dcp.write_key(0, &KEY).unwrap();
+    let ex: SingleChannel<Ch0> = SingleChannel::take(dcp).unwrap();
+    log::info!("DCP Init done");
+
+    let src_buf: DmaBuffer<32> = DmaBuffer::from_array(CIPHERTEXT);
+    let mut dest_buf: DmaBuffer<32> = DmaBuffer::new();
+    let mut payload: DmaBuffer<16> = DmaBuffer::new();
+
+    {
+        let builder = PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Cbc)
+            .key(KeySelect::Key0)
+            .payload(&mut payload)
+            .iv(&IV)
+            .tag(7)
+            .source(src_buf.as_source())
+            .dest(&mut dest_buf)
+            .decr_semaphore();
+
+        let mut packet: ControlPacket = builder.into();
+        log::info!("Queueing work packet on the DCP");
+        let task = ex.exec_one(&mut packet).unwrap();
+
+        let res = imxrt_dcp::block!(task.poll());
+        log::warn!("Operation result: {res:?}");
+    }
+
+    log::info!("Decrypted = {:X?}", &dest_buf[..]);
+    if dest_buf[..] == PLAINTEXT {
+        log::info!("Buffers match, decryption worked as expected.")
+    } else {
+        log::error!("Buffers don't match.");
+    }
+
+    loop {
+        asm::wfi()
+    }
+}
@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+use teensy4_bsp as bsp;
+use teensy4_panic as _;
+
+use cortex_m::{asm, delay::Delay, peripheral::syst::SystClkSource};
+use imxrt_dcp::{
+    ex::SingleChannel,
+    ops::Memcopy,
+    packet::ControlPacket,
+    prelude::*,
+};
+use teensy40_examples::logging;
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let ip = bsp::Peripherals::take().unwrap();
+    let mut delay = Delay::with_source(cp.SYST, bsp::EXT_SYSTICK_HZ, SystClkSource::External);
+    let mut ccm = ip.ccm.handle;
+
+    logging::init().unwrap();
+    delay.delay_ms(2000);
+
+    let dcp = dcp::Unclocked::take().unwrap().init(ccm.raw().0);
+    let ex: SingleChannel<Ch0> = SingleChannel::take(dcp).unwrap();
+    log::info!("DCP Init done");
+
+    // Three separate buffers gathered into a single one
+    let mut src_a: DmaBuffer<16> = DmaBuffer::new();
+    let mut src_b: DmaBuffer<32> = DmaBuffer::new();
+    let mut src_c: DmaBuffer<16> = DmaBuffer::new();
+    for (i, byte) in src_a.iter_mut().chain(src_b.iter_mut()).chain(src_c.iter_mut()).enumerate() {
+        *byte = i as u8;
+    }
+    let mut dest_buf: DmaBuffer<64> = DmaBuffer::new();
+
+    {
+        let (dest_a, rest) = dest_buf.split_at_mut(16);
+        let (dest_b, dest_c) = rest.split_at_mut(32);
+
+        // Only the last packet decrements the semaphore
+        let mut last: ControlPacket = PacketBuilder::<Memcopy>::new()
+            .tag(3)
+            .source(src_c.as_source())
+            .dest(dest_c)
+            .decr_semaphore()
+            .into();
+        let mut middle: ControlPacket = PacketBuilder::<Memcopy>::new()
+            .tag(2)
+            .source(src_b.as_source())
+            .dest(dest_b)
+            .chain_to(&mut last)
+            .into();
+        let mut head: ControlPacket = PacketBuilder::<Memcopy>::new()
+            .tag(1)
+            .source(src_a.as_source())
+            .dest(dest_a)
+            .chain_to(&mut middle)
+            .into();
+
+        log::info!("Queueing packet chain on the DCP");
+        let task = ex.exec_one(&mut head).unwrap();
+
+        let res = imxrt_dcp::block!(task.poll());
+        log::warn!("Operation result: {res:?}");
+    }
+
+    let gathered = src_a.iter().chain(src_b.iter()).chain(src_c.iter());
+    if gathered.eq(dest_buf.iter()) {
+        log::info!("Buffers match, the chain gathered 64 bytes.")
+    } else {
+        log::error!("Buffers don't match.")
+    }
+
+    loop {
+        asm::nop()
+    }
+}
@@ -8,7 +8,7 @@ use cortex_m::{asm, delay::Delay, peripheral::syst::SystClkSource};
 use imxrt_dcp::{
     ex::SingleChannel,
     ops::Memcopy,
-    packet::{ControlPacket, Source},
+    packet::ControlPacket,
     prelude::*,
 };
 use teensy40_examples::logging;
@@ -24,22 +24,20 @@ fn main() -> ! {
     delay.delay_ms(2000);
 
     // let dcp = _setup_dcp(&mut ccm);
-    let dcp = dcp::Unclocked::take().unwrap().clock(ccm.raw().0).build();
+    let dcp = dcp::Unclocked::take().unwrap().init(ccm.raw().0);
     let ex: SingleChannel<Ch0> = SingleChannel::take(dcp).unwrap();
     log::info!("DCP Init done");
 
-    let mut src_buf = [0u8; 64];
+    let mut src_buf: DmaBuffer<64> = DmaBuffer::new();
     for i in 0..64 {
         src_buf[i] = i as u8;
     }
-    let mut dest_buf = [0u8; 64];
+    let mut dest_buf: DmaBuffer<64> = DmaBuffer::new();
 
     {
-        let builder: PacketBuilder<Memcopy> = PacketBuilder::default()
+        let builder = PacketBuilder::<Memcopy>::new()
             .tag(7)
-            .source(Source {
-                pointer: &src_buf[0] as *const u8,
-            })
+            .source(src_buf.as_source())
             .dest(&mut dest_buf)
             .decr_semaphore();
 
@@ -51,7 +49,7 @@ fn main() -> ! {
         log::warn!("Operation result: {res:?}");
     }
 
-    if src_buf == dest_buf {
+    if *src_buf == *dest_buf {
         log::info!("Buffers match, some mysterious entity has copied 64 bytes.")
     } else {
         log::error!("Buffers don't match.")
@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+use teensy4_bsp as bsp;
+use teensy4_panic as _;
+
+use cortex_m::{asm, delay::Delay, peripheral::syst::SystClkSource};
+use imxrt_dcp::{
+    ex::SingleChannel,
+    ops::{Cipher, Hash, Sha256},
+    packet::{pipeline::Pipeline, KeySelect},
+    prelude::*,
+};
+use teensy40_examples::logging;
+
+// AES-128 CBC test vectors from NIST SP 800-38A, F.2.1
+const KEY: [u8; 16] = [
+    0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c,
+];
+const IV: [u8; 16] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+];
+const CIPHERTEXT: [u8; 32] = [
+    0x76, 0x49, 0xab, 0xac, 0x81, 0x19, 0xb2, 0x46, 0xce, 0xe9, 0x8e, 0x9b, 0x12, 0xe9, 0x19, 0x7d,
+    0x50, 0x86, 0xcb, 0x9b, 0x50, 0x72, 0x19, 0xee, 0x95, 0xdb, 0x11, 0x3a, 0x91, 0x76, 0x78, 0xb2,
+];
+const PLAINTEXT: [u8; 32] = [
+    0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a,
+    0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac, 0x45, 0xaf, 0x8e, 0x51,
+];
+// SHA-256 of the ciphertext
+const MAC: [u8; 32] = [
+    0x72, 0x01, 0x6a, 0x65, 0x86, 0x42, 0xa5, 0xef, 0x13, 0x5a, 0x2d, 0xe0, 0x98, 0x68, 0x54, 0x75,
+    0x37, 0x60, 0x44, 0xfa, 0x2f, 0x30, 0x3a, 0x7f, 0x82, 0x8a, 0xed, 0xc5, 0x4d, 0xf4, 0x86, 0xd0,
+];
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let ip = bsp::Peripherals::take().unwrap();
+    let mut delay = Delay::with_source(cp.SYST, bsp::EXT_SYSTICK_HZ, SystClkSource::External);
+    let mut ccm = ip.ccm.handle;
+
+    logging::init().unwrap();
+    delay.delay_ms(2000);
+
+    let dcp = dcp::Unclocked::take().unwrap().init(ccm.raw().0);
+    dcp.write_key(0, &KEY).unwrap();
+    let ex: SingleChannel<Ch0> = SingleChannel::take(dcp).unwrap();
+    log::info!("DCP Init done");
+
+    let src_buf: DmaBuffer<32> = DmaBuffer::from_array(PLAINTEXT);
+    let mut dest_buf: DmaBuffer<32> = DmaBuffer::new();
+    let mut cipher_payload: DmaBuffer<16> = DmaBuffer::new();
+    let mut hash_payload: DmaBuffer<32> = DmaBuffer::new();
+
+    let mac = {
+        // Encrypt into dest_buf, then hash dest_buf
+        let cipher = PacketBuilder::<Cipher>::new()
+            .cipher(Cipher::Aes128Cbc)
+            .key(KeySelect::Key0)
+            .encrypt()
+            .payload(&mut cipher_payload)
+            .iv(&IV)
+            .source(src_buf.as_source());
+        let hash = PacketBuilder::<Hash>::new()
+            .hash(Hash::Sha256)
+            .hash_init()
+            .hash_term()
+            .payload(&mut hash_payload);
+
+        let mut pipeline = Pipeline::new(cipher, hash, &mut dest_buf).unwrap();
+        log::info!("Queueing encrypt-then-MAC pipeline on the DCP");
+        let task = pipeline.exec(&ex).unwrap();
+
+        let res = imxrt_dcp::block!(task.poll());
+        log::warn!("Operation result: {res:?}");
+        task.digest::<Sha256>()
+    };
+
+    log::info!("Ciphertext = {:X?}", &dest_buf[..]);
+    log::info!("MAC = {mac:X?}");
+    if dest_buf[..] == CIPHERTEXT && mac == Some(MAC) {
+        log::info!("Ciphertext and MAC match, the pipeline worked as expected.")
+    } else {
+        log::error!("Ciphertext or MAC don't match.");
+    }
+
+    loop {
+        asm::wfi()
+    }
+}
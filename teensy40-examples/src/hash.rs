@@ -7,8 +7,8 @@ use teensy4_panic as _;
 use cortex_m::{asm, delay::Delay, peripheral::syst::SystClkSource};
 use imxrt_dcp::{
     ex::SingleChannel,
-    ops::Hash,
-    packet::{ControlPacket, Source},
+    ops::{Crc32, Hash},
+    packet::ControlPacket,
     prelude::*,
 };
 use teensy40_examples::logging;
@@ -24,7 +24,7 @@ fn main() -> ! {
     delay.delay_ms(2000);
 
     // let dcp = _setup_dcp(&mut ccm);
-    let dcp = dcp::Unclocked::take().unwrap().clock(ccm.raw().0).build();
+    let dcp = dcp::Unclocked::take().unwrap().init(ccm.raw().0);
     let ex: SingleChannel<Ch0> = SingleChannel::take(dcp).unwrap();
     log::info!("DCP Init done");
 
@@ -34,25 +34,16 @@ fn main() -> ! {
     }
     // stores calculated CRC32 hash
     let mut dest_buf = [0u8; 4];
-    // calculated with http://www.sunshine2k.de/coding/javascript/crc/crc_js.html
-    // Options:
-    // Input reflected:     false
-    // Result reflected:    false
-    // Polynomial:          0x4C11DB7
-    // Initial value:       0xFFFFFFFF
-    // Final XOR value:     0x000000000
-    // (Little endian)
+    // CRC-32/MPEG-2, calculated with http://www.sunshine2k.de/coding/javascript/crc/crc_js.html
     let expected_crc = 0xBCBD08F5u32;
 
-    {
-        let builder: PacketBuilder<Hash> = PacketBuilder::default()
+    let crc = {
+        let builder = PacketBuilder::<Hash>::new()
             .hash(Hash::Crc32)
             .hash_init()
             .hash_term()
             .tag(7)
-            .source(Source {
-                pointer: &src_buf[0] as *const u8,
-            })
+            .input(&src_buf)
             .payload(&mut dest_buf)
             .decr_semaphore();
 
@@ -62,14 +53,15 @@ fn main() -> ! {
 
         let res = imxrt_dcp::block!(task.poll());
         log::warn!("Operation result: {res:?}");
-    }
+        task.digest::<Crc32>()
+    };
 
-    log::info!("Calculatec CRC = {dest_buf:X?}");
-    log::info!("Expected CRC   = {:X?}", expected_crc.to_le_bytes());
-    if dest_buf == expected_crc.to_le_bytes() {
-        log::info!("Buffers match, CRC worked as expected.")
+    log::info!("Calculated CRC = {crc:X?}");
+    log::info!("Expected CRC   = {expected_crc:X}");
+    if crc == Some(expected_crc) {
+        log::info!("CRCs match, CRC worked as expected.")
     } else {
-        log::error!("Buffers don't match.");
+        log::error!("CRCs don't match.");
     }
 
     loop {